@@ -0,0 +1,55 @@
+//! Compares the queues of this crate under the same workload.
+//!
+//! `TsQueue`, `SegmentedTsQueue` and `LockFreeQueue` are all driven through
+//! the `Queue` trait, once with one producer and one consumer and once with
+//! four of each.
+//!
+//! Run with `cargo run --release --example compare`. Contention, and with it
+//! the difference between the lock-based and the lock-free queues, only shows
+//! with several cores.
+
+use std::thread;
+use std::time::{Duration, Instant};
+use ts_queue::{LockFreeQueue, Queue, SegmentedTsQueue, TsQueue};
+
+const OPS: usize = 4_000_000;
+
+fn throughput<Q: Queue<usize>>(threads: usize) -> Duration {
+    let queue = Q::new();
+    let per_thread = OPS / threads;
+    let start = Instant::now();
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for i in 0..per_thread {
+                    queue.enqueue(i).unwrap();
+                }
+            });
+            s.spawn(|| {
+                for _ in 0..per_thread {
+                    while queue.dequeue().is_none() {
+                        thread::yield_now();
+                    }
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn report<Q: Queue<usize>>(name: &str) {
+    for threads in [1, 4] {
+        let elapsed = throughput::<Q>(threads);
+        let mops = OPS as f64 / elapsed.as_secs_f64() / 1e6;
+        println!(
+            "{:<20} {}P/{}C {:>8.1?} {:>8.2} Mops/s",
+            name, threads, threads, elapsed, mops
+        );
+    }
+}
+
+fn main() {
+    report::<TsQueue<usize>>("TsQueue");
+    report::<SegmentedTsQueue<usize>>("SegmentedTsQueue");
+    report::<LockFreeQueue<usize>>("LockFreeQueue");
+}
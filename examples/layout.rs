@@ -0,0 +1,238 @@
+//! Measures the effect of the memory layout of `TsQueue`.
+//!
+//! `TsQueue`'s internals are private, so this example contains a copy of its
+//! two-lock algorithm that can be instantiated with the layout `TsQueue` had
+//! before, with `head`, `tail` and `len` next to each other and a node storing
+//! `Option<T>`, and with the current one, with each of them on its own cache
+//! line and a node storing `MaybeUninit<T>` behind `next`. Both copies and
+//! `TsQueue` itself run the same workload of one producer and one consumer.
+//!
+//! Run with `cargo run --release --example layout`. The gain depends on the
+//! machine and only shows with at least two cores.
+
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ops::Deref;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use ts_queue::TsQueue;
+
+const OPS: usize = 5_000_000;
+
+trait Slot<T>: Deref<Target = T> {
+    fn new(value: T) -> Self;
+}
+
+struct Plain<T>(T);
+
+#[repr(align(128))]
+struct Padded<T>(T);
+
+impl<T> Deref for Plain<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> Slot<T> for Plain<T> {
+    fn new(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> Deref for Padded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> Slot<T> for Padded<T> {
+    fn new(value: T) -> Self {
+        Self(value)
+    }
+}
+
+trait Node: Sized {
+    fn empty() -> *mut Self;
+    fn next(&self) -> &AtomicPtr<Self>;
+    // Safety: `node` must be the current tail.
+    unsafe fn write(node: *mut Self, data: usize);
+    // Safety: `node` must be the unlinked former head.
+    unsafe fn take(node: *mut Self) -> usize;
+}
+
+struct OldNode {
+    data: Option<usize>,
+    next: AtomicPtr<OldNode>,
+}
+
+impl Node for OldNode {
+    fn empty() -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            data: None,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+
+    fn next(&self) -> &AtomicPtr<Self> {
+        &self.next
+    }
+
+    unsafe fn write(node: *mut Self, data: usize) {
+        (*node).data = Some(data);
+    }
+
+    unsafe fn take(node: *mut Self) -> usize {
+        Box::from_raw(node).data.unwrap()
+    }
+}
+
+#[repr(C)]
+struct NewNode {
+    next: AtomicPtr<NewNode>,
+    data: MaybeUninit<usize>,
+}
+
+impl Node for NewNode {
+    fn empty() -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            next: AtomicPtr::new(ptr::null_mut()),
+            data: MaybeUninit::uninit(),
+        }))
+    }
+
+    fn next(&self) -> &AtomicPtr<Self> {
+        &self.next
+    }
+
+    unsafe fn write(node: *mut Self, data: usize) {
+        (*node).data = MaybeUninit::new(data);
+    }
+
+    unsafe fn take(node: *mut Self) -> usize {
+        Box::from_raw(node).data.assume_init()
+    }
+}
+
+struct Link<N>(*mut N);
+
+unsafe impl<N> Send for Link<N> {}
+
+// Same algorithm as `TsQueue`: data is stored in the old tail node, and
+// consumers detect a non-empty queue from `next` of the head node.
+struct Queue<N, H: Deref<Target = Mutex<Link<N>>>, L> {
+    head: H,
+    tail: H,
+    len: L,
+    _node: PhantomData<N>,
+}
+
+unsafe impl<N, H: Deref<Target = Mutex<Link<N>>> + Sync, L: Sync> Sync for Queue<N, H, L> {}
+
+type OldQueue = Queue<OldNode, Plain<Mutex<Link<OldNode>>>, Plain<AtomicUsize>>;
+type NewQueue = Queue<NewNode, Padded<Mutex<Link<NewNode>>>, Padded<AtomicUsize>>;
+
+impl<N, H, L> Queue<N, H, L>
+where
+    N: Node,
+    H: Slot<Mutex<Link<N>>>,
+    L: Slot<AtomicUsize>,
+{
+    fn new() -> Self {
+        let dummy = N::empty();
+        Self {
+            head: H::new(Mutex::new(Link(dummy))),
+            tail: H::new(Mutex::new(Link(dummy))),
+            len: L::new(AtomicUsize::new(0)),
+            _node: PhantomData,
+        }
+    }
+
+    fn enqueue(&self, data: usize) {
+        let node = N::empty();
+        let mut tail = self.tail.lock().unwrap();
+        self.len.fetch_add(1, Ordering::Relaxed);
+        unsafe {
+            N::write(tail.0, data);
+            (*tail.0).next().store(node, Ordering::Release);
+        }
+        tail.0 = node;
+    }
+
+    fn dequeue(&self) -> Option<usize> {
+        let mut head = self.head.lock().unwrap();
+        let old_head = head.0;
+        let next = unsafe { (*old_head).next().load(Ordering::Acquire) };
+        if next.is_null() {
+            return None;
+        }
+        head.0 = next;
+        drop(head);
+        self.len.fetch_sub(1, Ordering::Relaxed);
+        Some(unsafe { N::take(old_head) })
+    }
+}
+
+impl<N, H: Deref<Target = Mutex<Link<N>>>, L> Drop for Queue<N, H, L> {
+    fn drop(&mut self) {
+        // Only the dummy is left after the workload drained the queue.
+        drop(unsafe { Box::from_raw(self.head.lock().unwrap().0) });
+    }
+}
+
+fn throughput<Q: Sync>(
+    queue: &Q,
+    enqueue: impl Fn(&Q, usize) + Send + Sync,
+    dequeue: impl Fn(&Q) -> Option<usize> + Send + Sync,
+) -> Duration {
+    let start = Instant::now();
+    thread::scope(|s| {
+        s.spawn(|| {
+            for i in 0..OPS {
+                enqueue(queue, i);
+            }
+        });
+        s.spawn(|| {
+            for _ in 0..OPS {
+                while dequeue(queue).is_none() {
+                    thread::yield_now();
+                }
+            }
+        });
+    });
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let mops = OPS as f64 / elapsed.as_secs_f64() / 1e6;
+    println!("{:<24} {:>8.1?} {:>8.2} Mops/s", name, elapsed, mops);
+}
+
+fn main() {
+    let old = OldQueue::new();
+    report(
+        "old layout 1P/1C",
+        throughput(&old, OldQueue::enqueue, OldQueue::dequeue),
+    );
+    let new = NewQueue::new();
+    report(
+        "new layout 1P/1C",
+        throughput(&new, NewQueue::enqueue, NewQueue::dequeue),
+    );
+    let queue = TsQueue::new();
+    report(
+        "TsQueue 1P/1C",
+        throughput(
+            &queue,
+            |queue, i| queue.enqueue(i).unwrap(),
+            |queue| queue.dequeue().ok(),
+        ),
+    );
+}
@@ -0,0 +1,66 @@
+use crate::pool::NodePool;
+use crate::{DefaultRawMutex, RawMutex, TsQueue};
+use core::fmt;
+use core::marker::PhantomData;
+
+/// Builder for a [`TsQueue`] with non-default configuration, created by
+/// [`TsQueue::builder`].
+pub struct Builder<T, R = DefaultRawMutex> {
+    capacity: Option<usize>,
+    node_pool: Option<usize>,
+    _marker: PhantomData<fn() -> (T, R)>,
+}
+
+impl<T> Builder<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            capacity: None,
+            node_pool: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T, R: RawMutex> Builder<T, R> {
+    /// Limits the queue to `capacity` elements, see [`TsQueue::bounded`].
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Recycles up to `max_nodes` dequeued nodes for later enqueues instead of
+    /// freeing and reallocating them. Statistics are available through
+    /// [`TsQueue::pool_stats`].
+    pub fn node_pool(mut self, max_nodes: usize) -> Self {
+        self.node_pool = Some(max_nodes);
+        self
+    }
+
+    /// Uses `L` for the head and tail locks instead of the
+    /// [`DefaultRawMutex`], e.g. [`SpinLock`](crate::SpinLock).
+    pub fn lock<L: RawMutex>(self) -> Builder<T, L> {
+        Builder {
+            capacity: self.capacity,
+            node_pool: self.node_pool,
+            _marker: PhantomData,
+        }
+    }
+
+    /// # Panics
+    /// Panics if a capacity of zero was configured.
+    pub fn build(self) -> TsQueue<T, R> {
+        if let Some(capacity) = self.capacity {
+            assert!(capacity > 0, "capacity of a bounded queue must be non-zero");
+        }
+        TsQueue::with_config(self.capacity, self.node_pool.map(NodePool::new))
+    }
+}
+
+impl<T, R> fmt::Debug for Builder<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Builder")
+            .field("capacity", &self.capacity)
+            .field("node_pool", &self.node_pool)
+            .finish()
+    }
+}
@@ -0,0 +1,270 @@
+#[cfg(feature = "std")]
+use crate::DequeueTimeoutError;
+use crate::{
+    Closed, DequeueError, Drain, RecvFuture, SendFuture, TryDequeueError, TryEnqueueError, TryIter,
+    TsQueue,
+};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// Creates a channel backed by an unbounded [`TsQueue`].
+///
+/// Both handles can be cloned. Dropping the last [`Sender`] closes the queue,
+/// so receivers drain the remaining elements and then observe the closed
+/// queue. Dropping the last [`Receiver`] also closes the queue, which makes
+/// further enqueues fail.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: TsQueue::new(),
+        senders: AtomicUsize::new(1),
+        receivers: AtomicUsize::new(1),
+    });
+    let sender = Sender {
+        shared: shared.clone(),
+        #[cfg(feature = "futures")]
+        pending: None,
+        #[cfg(feature = "futures")]
+        send_key: None,
+        #[cfg(feature = "futures")]
+        disconnected: false,
+    };
+    let receiver = Receiver {
+        shared,
+        #[cfg(feature = "futures")]
+        recv_key: None,
+    };
+    (sender, receiver)
+}
+
+pub(crate) struct Shared<T> {
+    pub(crate) queue: TsQueue<T>,
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+}
+
+pub struct Sender<T> {
+    pub(crate) shared: Arc<Shared<T>>,
+    // State of the `Sink` implementation: the element accepted by
+    // `start_send` but not yet enqueued, the waker registration while
+    // waiting for space and whether the sink has been closed, which gives up
+    // this handle's share in keeping the queue open.
+    #[cfg(feature = "futures")]
+    pub(crate) pending: Option<T>,
+    #[cfg(feature = "futures")]
+    pub(crate) send_key: Option<usize>,
+    #[cfg(feature = "futures")]
+    pub(crate) disconnected: bool,
+}
+
+// The element buffered by the `Sink` implementation is never pinned.
+impl<T> Unpin for Sender<T> {}
+
+impl<T> Sender<T> {
+    pub fn enqueue(&self, data: T) -> Result<(), Closed<T>> {
+        self.shared.queue.enqueue(data)
+    }
+
+    pub fn try_enqueue(&self, data: T) -> Result<(), TryEnqueueError<T>> {
+        self.shared.queue.try_enqueue(data)
+    }
+
+    pub fn enqueue_batch(&self, items: impl IntoIterator<Item = T>) -> Result<(), Closed<Vec<T>>> {
+        self.shared.queue.enqueue_batch(items)
+    }
+
+    pub fn send(&self, data: T) -> SendFuture<'_, T> {
+        self.shared.queue.send(data)
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shared.queue.is_empty()
+    }
+
+    /// Returns `true` if all receivers have been dropped or the queue was
+    /// closed otherwise.
+    pub fn is_closed(&self) -> bool {
+        self.shared.queue.is_closed()
+    }
+
+    // Gives up this handle's share in keeping the queue open and closes it if
+    // this was the last sender. Must be called at most once per handle.
+    pub(crate) fn disconnect(&self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.queue.close();
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: self.shared.clone(),
+            #[cfg(feature = "futures")]
+            pending: None,
+            #[cfg(feature = "futures")]
+            send_key: None,
+            #[cfg(feature = "futures")]
+            disconnected: false,
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "futures")]
+        {
+            if let Some(key) = self.send_key.take() {
+                self.shared.queue.send_wakers.cancel(key);
+            }
+            if self.disconnected {
+                return;
+            }
+        }
+        self.disconnect();
+    }
+}
+
+pub struct Receiver<T> {
+    pub(crate) shared: Arc<Shared<T>>,
+    // Waker registration of the `Stream` implementation.
+    #[cfg(feature = "futures")]
+    pub(crate) recv_key: Option<usize>,
+}
+
+impl<T> Receiver<T> {
+    pub fn dequeue(&self) -> Result<T, DequeueError> {
+        self.shared.queue.dequeue()
+    }
+
+    pub fn try_dequeue(&self) -> Result<T, TryDequeueError> {
+        self.shared.queue.try_dequeue()
+    }
+
+    pub fn dequeue_blocking(&self) -> Option<T> {
+        self.shared.queue.dequeue_blocking()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn dequeue_timeout(&self, timeout: Duration) -> Result<T, DequeueTimeoutError> {
+        self.shared.queue.dequeue_timeout(timeout)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn dequeue_deadline(&self, deadline: Instant) -> Result<T, DequeueTimeoutError> {
+        self.shared.queue.dequeue_deadline(deadline)
+    }
+
+    pub fn dequeue_batch(&self, max: usize, out: &mut Vec<T>) -> usize {
+        self.shared.queue.dequeue_batch(max, out)
+    }
+
+    pub fn drain_all(&self) -> Vec<T> {
+        self.shared.queue.drain_all()
+    }
+
+    pub fn drain(&self) -> Drain<'_, T> {
+        self.shared.queue.drain()
+    }
+
+    pub fn try_iter(&self) -> TryIter<'_, T> {
+        self.shared.queue.try_iter()
+    }
+
+    pub fn peek_with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.shared.queue.peek_with(f)
+    }
+
+    pub fn recv(&self) -> RecvFuture<'_, T> {
+        self.shared.queue.recv()
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shared.queue.is_empty()
+    }
+
+    /// Returns `true` if all senders have been dropped or the queue was closed
+    /// otherwise.
+    pub fn is_closed(&self) -> bool {
+        self.shared.queue.is_closed()
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.receivers.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: self.shared.clone(),
+            #[cfg(feature = "futures")]
+            recv_key: None,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "futures")]
+        if let Some(key) = self.recv_key.take() {
+            self.shared.queue.recv_wakers.cancel(key);
+        }
+        if self.shared.receivers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.queue.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{channel, Closed, DequeueError};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn dropping_senders_closes() {
+        let (tx, rx) = channel();
+        let tx2 = tx.clone();
+        tx.enqueue(1).unwrap();
+        drop(tx);
+        assert!(!rx.is_closed());
+        tx2.enqueue(2).unwrap();
+
+        // Dropping the last sender wakes up a blocked receiver.
+        thread::scope(|s| {
+            let consumer = s.spawn(|| {
+                let mut data = vec![];
+                while let Some(i) = rx.dequeue_blocking() {
+                    data.push(i);
+                }
+                data
+            });
+            thread::sleep(Duration::from_millis(10));
+            drop(tx2);
+            assert_eq!(consumer.join().unwrap(), [1, 2]);
+        });
+        assert!(rx.is_closed());
+        assert_eq!(rx.dequeue(), Err(DequeueError::Closed));
+    }
+
+    #[test]
+    fn dropping_receivers_fails_enqueue() {
+        let (tx, rx) = channel();
+        let rx2 = rx.clone();
+        drop(rx);
+        tx.enqueue(1).unwrap();
+        assert_eq!(rx2.dequeue(), Ok(1));
+        drop(rx2);
+        assert!(tx.is_closed());
+        assert_eq!(tx.enqueue(2), Err(Closed(2)));
+    }
+}
@@ -0,0 +1,87 @@
+use crate::lock::{DefaultRawMutex, Mutex};
+use alloc::sync::Arc;
+use core::fmt;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+/// Source of the current time, used by [`TsPriorityQueue`](crate::TsPriorityQueue)
+/// to determine how long an element has been waiting.
+pub trait Clock: Send + Sync {
+    /// Returns the time elapsed since an arbitrary but fixed origin. Must never
+    /// decrease.
+    fn now(&self) -> Duration;
+}
+
+/// [`Clock`] measuring time with [`Instant`], starting at its creation.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    origin: Instant,
+}
+
+#[cfg(feature = "std")]
+impl MonotonicClock {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for MonotonicClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// [`Clock`] that only moves when [`advance`](Self::advance) is called, e.g.
+/// to test aging deterministically. Clones share the same time.
+#[derive(Clone)]
+pub struct ManualClock {
+    // Behind a lock rather than in an `AtomicU64`, which not all targets have.
+    now: Arc<Mutex<DefaultRawMutex, Duration>>,
+}
+
+impl ManualClock {
+    /// Creates a clock starting at zero.
+    pub fn new() -> Self {
+        Self {
+            now: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    /// Moves the clock forward by `by`, saturating at [`Duration::MAX`].
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.now.lock();
+        *now = now.saturating_add(by);
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ManualClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ManualClock")
+            .field("now", &self.now())
+            .finish()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        *self.now.lock()
+    }
+}
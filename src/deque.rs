@@ -0,0 +1,198 @@
+use crate::lock::{DefaultRawMutex, Mutex};
+use alloc::boxed::Box;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Thread-safe double-ended queue backed by a doubly linked list.
+///
+/// Unlike [`TsQueue`](crate::TsQueue), both ends can reach the same node, so a
+/// single lock guards the whole list. Nodes are allocated and freed outside of
+/// it, which keeps the critical sections down to relinking a few pointers.
+pub struct TsDeque<T> {
+    list: Mutex<DefaultRawMutex, List<T>>,
+    len: AtomicUsize,
+}
+
+struct List<T> {
+    front: Option<NonNull<Node<T>>>,
+    back: Option<NonNull<Node<T>>>,
+}
+
+struct Node<T> {
+    data: T,
+    prev: Option<NonNull<Node<T>>>,
+    next: Option<NonNull<Node<T>>>,
+}
+
+unsafe impl<T: Send> Send for TsDeque<T> {}
+unsafe impl<T: Send> Sync for TsDeque<T> {}
+
+impl<T> Default for TsDeque<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> TsDeque<T> {
+    pub fn new() -> Self {
+        Self {
+            list: Mutex::new(List {
+                front: None,
+                back: None,
+            }),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push_front(&self, data: T) {
+        let node = Self::alloc(data);
+        let mut list = self.list.lock();
+        unsafe { (*node.as_ptr()).next = list.front };
+        match list.front {
+            Some(front) => unsafe { (*front.as_ptr()).prev = Some(node) },
+            None => list.back = Some(node),
+        }
+        list.front = Some(node);
+        self.len.fetch_add(1, Ordering::Release);
+    }
+
+    pub fn push_back(&self, data: T) {
+        let node = Self::alloc(data);
+        let mut list = self.list.lock();
+        unsafe { (*node.as_ptr()).prev = list.back };
+        match list.back {
+            Some(back) => unsafe { (*back.as_ptr()).next = Some(node) },
+            None => list.front = Some(node),
+        }
+        list.back = Some(node);
+        self.len.fetch_add(1, Ordering::Release);
+    }
+
+    pub fn pop_front(&self) -> Option<T> {
+        let mut list = self.list.lock();
+        let node = list.front?;
+        list.front = unsafe { (*node.as_ptr()).next };
+        match list.front {
+            Some(front) => unsafe { (*front.as_ptr()).prev = None },
+            None => list.back = None,
+        }
+        self.len.fetch_sub(1, Ordering::Release);
+        drop(list);
+        Some(unsafe { Box::from_raw(node.as_ptr()) }.data)
+    }
+
+    pub fn pop_back(&self) -> Option<T> {
+        let mut list = self.list.lock();
+        let node = list.back?;
+        list.back = unsafe { (*node.as_ptr()).prev };
+        match list.back {
+            Some(back) => unsafe { (*back.as_ptr()).next = None },
+            None => list.front = None,
+        }
+        self.len.fetch_sub(1, Ordering::Release);
+        drop(list);
+        Some(unsafe { Box::from_raw(node.as_ptr()) }.data)
+    }
+
+    fn alloc(data: T) -> NonNull<Node<T>> {
+        Box::leak(Box::new(Node {
+            data,
+            prev: None,
+            next: None,
+        }))
+        .into()
+    }
+}
+
+impl<T> Drop for TsDeque<T> {
+    fn drop(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TsDeque;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn single_threaded() {
+        let deque = TsDeque::new();
+        assert_eq!(deque.pop_front(), None);
+        assert_eq!(deque.pop_back(), None);
+        deque.push_back(2);
+        deque.push_back(3);
+        deque.push_front(1);
+        deque.push_front(0);
+        assert_eq!(deque.len(), 4);
+        assert_eq!(deque.pop_back(), Some(3));
+        assert_eq!(deque.pop_front(), Some(0));
+        assert_eq!(deque.pop_back(), Some(2));
+        assert_eq!(deque.pop_back(), Some(1));
+        assert!(deque.is_empty());
+        deque.push_front(4);
+        assert_eq!(deque.pop_back(), Some(4));
+        assert_eq!(deque.pop_front(), None);
+    }
+
+    #[test]
+    fn both_ends_concurrently() {
+        let deque = TsDeque::new();
+        let data_expected: Vec<_> = (0..10000).collect();
+
+        let (mut front, mut back) = thread::scope(|s| {
+            s.spawn(|| {
+                for i in &data_expected[..5000] {
+                    deque.push_front(*i);
+                }
+            });
+            s.spawn(|| {
+                for i in &data_expected[5000..] {
+                    deque.push_back(*i);
+                }
+            });
+            let front = s.spawn(|| {
+                let mut data = vec![];
+                for _ in 0..2000 {
+                    data.extend(deque.pop_front());
+                }
+                data
+            });
+            let back = s.spawn(|| {
+                let mut data = vec![];
+                for _ in 0..2000 {
+                    data.extend(deque.pop_back());
+                }
+                data
+            });
+            (front.join().unwrap(), back.join().unwrap())
+        });
+
+        front.append(&mut back);
+        front.extend(std::iter::from_fn(|| deque.pop_front()));
+        front.sort_unstable();
+        assert_eq!(front, data_expected);
+    }
+
+    #[test]
+    fn drops_remaining_elements() {
+        let counter = Arc::new(());
+        let deque = TsDeque::new();
+        for _ in 0..5 {
+            deque.push_back(counter.clone());
+            deque.push_front(counter.clone());
+        }
+        drop(deque.pop_back());
+        drop(deque);
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+}
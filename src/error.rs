@@ -0,0 +1,147 @@
+use core::error::Error;
+use core::fmt;
+
+/// Error returned by [`TsQueue::enqueue`](crate::TsQueue::enqueue) on a closed
+/// queue. Contains the rejected value.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct Closed<T>(pub T);
+
+impl<T> Closed<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> fmt::Debug for Closed<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Closed(..)")
+    }
+}
+
+impl<T> fmt::Display for Closed<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("enqueue on a closed queue")
+    }
+}
+
+impl<T> Error for Closed<T> {}
+
+/// Error returned by [`TsQueue::try_enqueue`](crate::TsQueue::try_enqueue).
+/// All variants contain the rejected value.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum TryEnqueueError<T> {
+    Full(T),
+    Closed(T),
+    /// A thread panicked while holding a lock of the queue, see
+    /// [`TsQueue::clear_poison`](crate::TsQueue::clear_poison).
+    Poisoned(T),
+}
+
+impl<T> TryEnqueueError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            Self::Full(data) | Self::Closed(data) | Self::Poisoned(data) => data,
+        }
+    }
+}
+
+impl<T> From<Closed<T>> for TryEnqueueError<T> {
+    fn from(err: Closed<T>) -> Self {
+        Self::Closed(err.0)
+    }
+}
+
+impl<T> fmt::Debug for TryEnqueueError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => f.write_str("Full(..)"),
+            Self::Closed(_) => f.write_str("Closed(..)"),
+            Self::Poisoned(_) => f.write_str("Poisoned(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for TryEnqueueError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => f.write_str("enqueue on a full queue"),
+            Self::Closed(_) => f.write_str("enqueue on a closed queue"),
+            Self::Poisoned(_) => f.write_str("enqueue on a poisoned queue"),
+        }
+    }
+}
+
+impl<T> Error for TryEnqueueError<T> {}
+
+/// Error returned by [`TsQueue::dequeue`](crate::TsQueue::dequeue).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DequeueError {
+    /// The queue is momentarily empty.
+    Empty,
+    /// The queue is empty and has been closed.
+    Closed,
+}
+
+impl fmt::Display for DequeueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("dequeue on an empty queue"),
+            Self::Closed => f.write_str("dequeue on an empty and closed queue"),
+        }
+    }
+}
+
+impl Error for DequeueError {}
+
+/// Error returned by [`TsQueue::try_dequeue`](crate::TsQueue::try_dequeue).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryDequeueError {
+    /// The queue is momentarily empty.
+    Empty,
+    /// The queue is empty and has been closed.
+    Closed,
+    /// A thread panicked while holding a lock of the queue, see
+    /// [`TsQueue::clear_poison`](crate::TsQueue::clear_poison).
+    Poisoned,
+}
+
+impl From<DequeueError> for TryDequeueError {
+    fn from(err: DequeueError) -> Self {
+        match err {
+            DequeueError::Empty => Self::Empty,
+            DequeueError::Closed => Self::Closed,
+        }
+    }
+}
+
+impl fmt::Display for TryDequeueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("dequeue on an empty queue"),
+            Self::Closed => f.write_str("dequeue on an empty and closed queue"),
+            Self::Poisoned => f.write_str("dequeue on a poisoned queue"),
+        }
+    }
+}
+
+impl Error for TryDequeueError {}
+
+/// Error returned by the timed dequeue operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DequeueTimeoutError {
+    /// No element became available in time.
+    Timeout,
+    /// The queue is empty and has been closed.
+    Closed,
+}
+
+impl fmt::Display for DequeueTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => f.write_str("timed out waiting on an empty queue"),
+            Self::Closed => f.write_str("dequeue on an empty and closed queue"),
+        }
+    }
+}
+
+impl Error for DequeueTimeoutError {}
@@ -0,0 +1,151 @@
+#[cfg(feature = "std")]
+use crate::error::DequeueTimeoutError;
+use crate::error::{DequeueError, TryEnqueueError};
+use crate::lock::{Condvar, MutexGuard, RawMutex};
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+// Capacity, closed flag and condition variables of a blocking queue, shared by
+// `TsQueue` and `TsPriorityQueue`.
+//
+// Each queue pairs them with one of its locks: producers check the flag and
+// the length under it, consumers hold it from observing the queue empty until
+// they wait on `not_empty`, and `close` sets the flag under it. That way
+// neither an enqueue nor a close can slip in unnoticed before a wait.
+pub(crate) struct FlowControl {
+    capacity: Option<usize>,
+    closed: AtomicBool,
+    pub(crate) not_empty: Condvar,
+    pub(crate) not_full: Condvar,
+}
+
+impl FlowControl {
+    pub(crate) fn new(capacity: Option<usize>) -> Self {
+        Self {
+            capacity,
+            closed: AtomicBool::new(false),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    pub(crate) fn is_full(&self, len: usize) -> bool {
+        self.capacity.is_some_and(|capacity| len >= capacity)
+    }
+
+    pub(crate) fn free_slots(&self, len: usize) -> usize {
+        match self.capacity {
+            Some(capacity) => capacity.saturating_sub(len),
+            None => usize::MAX,
+        }
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    // Waits on `not_full` while the queue is full. Returns `None` with the lock
+    // released if the queue is closed.
+    pub(crate) fn wait_for_room<'a, R: RawMutex, G>(
+        &self,
+        mut guard: MutexGuard<'a, R, G>,
+        len: impl Fn() -> usize,
+    ) -> Option<MutexGuard<'a, R, G>> {
+        loop {
+            if self.is_closed() {
+                return None;
+            }
+            if !self.is_full(len()) {
+                return Some(guard);
+            }
+            guard = self.not_full.wait(guard);
+        }
+    }
+
+    // Hands `data` back if a non-blocking enqueue may proceed with `len`
+    // elements in the queue.
+    pub(crate) fn check_room<T>(&self, data: T, len: usize) -> Result<T, TryEnqueueError<T>> {
+        if self.is_closed() {
+            Err(TryEnqueueError::Closed(data))
+        } else if self.is_full(len) {
+            Err(TryEnqueueError::Full(data))
+        } else {
+            Ok(data)
+        }
+    }
+
+    pub(crate) fn dequeue<T>(&self, pop: impl FnOnce() -> Option<T>) -> Result<T, DequeueError> {
+        // Read before looking at the queue: all elements enqueued before the
+        // queue was closed are visible once the flag is.
+        let closed = self.is_closed();
+        match pop() {
+            Some(data) => Ok(data),
+            None if closed => Err(DequeueError::Closed),
+            None => Err(DequeueError::Empty),
+        }
+    }
+
+    // Retries `pop` until it succeeds or the queue is closed. A failed `pop`
+    // returns the guard of the paired lock, which is held while going to sleep.
+    pub(crate) fn dequeue_blocking<'a, R: RawMutex + 'a, G: 'a, T>(
+        &self,
+        mut pop: impl FnMut() -> Result<T, MutexGuard<'a, R, G>>,
+    ) -> Option<T> {
+        loop {
+            match pop() {
+                Ok(data) => return Some(data),
+                Err(_) if self.is_closed() => return None,
+                Err(guard) => drop(self.not_empty.wait(guard)),
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn dequeue_timeout<'a, R: RawMutex + 'a, G: 'a, T>(
+        &self,
+        timeout: Duration,
+        pop: impl FnMut() -> Result<T, MutexGuard<'a, R, G>>,
+    ) -> Result<T, DequeueTimeoutError> {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.dequeue_deadline(deadline, pop),
+            None => self
+                .dequeue_blocking(pop)
+                .ok_or(DequeueTimeoutError::Closed),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn dequeue_deadline<'a, R: RawMutex + 'a, G: 'a, T>(
+        &self,
+        deadline: Instant,
+        mut pop: impl FnMut() -> Result<T, MutexGuard<'a, R, G>>,
+    ) -> Result<T, DequeueTimeoutError> {
+        loop {
+            match pop() {
+                Ok(data) => return Ok(data),
+                Err(_) if self.is_closed() => return Err(DequeueTimeoutError::Closed),
+                Err(guard) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(DequeueTimeoutError::Timeout);
+                    }
+                    drop(self.not_empty.wait_timeout(guard, deadline - now));
+                }
+            }
+        }
+    }
+
+    // Sets the closed flag while the paired lock is held and wakes up all
+    // waiting threads.
+    pub(crate) fn close<G>(&self, guard: G) {
+        self.closed.store(true, Ordering::Release);
+        drop(guard);
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+}
@@ -0,0 +1,209 @@
+use crate::{Closed, DefaultRawMutex, RawMutex, TsQueue};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+impl<T, R: RawMutex> TsQueue<T, R> {
+    /// Dequeues the front element asynchronously. Resolves to `None` once the
+    /// queue is closed and drained.
+    ///
+    /// The returned future does not depend on a particular runtime, it is
+    /// woken by `enqueue` and `close`.
+    pub fn recv(&self) -> RecvFuture<'_, T, R> {
+        RecvFuture {
+            queue: self,
+            key: None,
+        }
+    }
+
+    /// Enqueues `data` asynchronously, waiting while a bounded queue is full.
+    /// Resolves to an error if the queue has been closed.
+    pub fn send(&self, data: T) -> SendFuture<'_, T, R> {
+        SendFuture {
+            queue: self,
+            data: Some(data),
+            key: None,
+        }
+    }
+
+    pub(crate) fn poll_dequeue(
+        &self,
+        cx: &mut Context<'_>,
+        key: &mut Option<usize>,
+    ) -> Poll<Option<T>> {
+        let ready = match self.pop_or_tail_guard() {
+            Ok(data) => Some(data),
+            Err(_) if self.is_closed() => None,
+            Err(tail) => {
+                // Registering while the tail lock is held ensures the next
+                // `enqueue` or `close` sees this task.
+                self.recv_wakers.register(key, cx.waker());
+                drop(tail);
+                return Poll::Pending;
+            }
+        };
+        if let Some(key) = key.take() {
+            self.recv_wakers.remove(key);
+        }
+        Poll::Ready(ready)
+    }
+
+    pub(crate) fn poll_enqueue(
+        &self,
+        cx: &mut Context<'_>,
+        data: &mut Option<T>,
+        key: &mut Option<usize>,
+    ) -> Poll<Result<(), Closed<T>>> {
+        let node = self.alloc_node();
+        let tail = self.lock_tail();
+        let ready = if self.is_closed() {
+            drop(tail);
+            unsafe { self.free_unused_node(node) };
+            Err(Closed(data.take().expect("polled after completion")))
+        } else if self.is_full() {
+            self.send_wakers.register(key, cx.waker());
+            drop(tail);
+            unsafe { self.free_unused_node(node) };
+            return Poll::Pending;
+        } else {
+            let data = data.take().expect("polled after completion");
+            self.push(tail, node, data);
+            Ok(())
+        };
+        if let Some(key) = key.take() {
+            self.send_wakers.remove(key);
+        }
+        Poll::Ready(ready)
+    }
+}
+
+/// Future returned by [`TsQueue::recv`].
+pub struct RecvFuture<'a, T, R: RawMutex = DefaultRawMutex> {
+    queue: &'a TsQueue<T, R>,
+    key: Option<usize>,
+}
+
+impl<T, R: RawMutex> Future for RecvFuture<'_, T, R> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.queue.poll_dequeue(cx, &mut this.key)
+    }
+}
+
+impl<T, R: RawMutex> Drop for RecvFuture<'_, T, R> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.queue.recv_wakers.cancel(key);
+        }
+    }
+}
+
+/// Future returned by [`TsQueue::send`].
+pub struct SendFuture<'a, T, R: RawMutex = DefaultRawMutex> {
+    queue: &'a TsQueue<T, R>,
+    data: Option<T>,
+    key: Option<usize>,
+}
+
+// The pending data is never pinned.
+impl<T, R: RawMutex> Unpin for SendFuture<'_, T, R> {}
+
+impl<T, R: RawMutex> Future for SendFuture<'_, T, R> {
+    type Output = Result<(), Closed<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let data = &mut this.data;
+        let key = &mut this.key;
+        this.queue.poll_enqueue(cx, data, key)
+    }
+}
+
+impl<T, R: RawMutex> Drop for SendFuture<'_, T, R> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.queue.send_wakers.cancel(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::roundtrip;
+    use crate::{Closed, TsQueue};
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread::{self, Thread};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    // Minimal executor driving a single future on the current thread.
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = pin!(fut);
+        loop {
+            if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[derive(Default)]
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn recv_and_send() {
+        let queue = TsQueue::bounded(4);
+        roundtrip(
+            |i| block_on(queue.send(i)).unwrap(),
+            || block_on(queue.recv()),
+        );
+        queue.close();
+        assert_eq!(block_on(queue.recv()), None);
+        assert_eq!(block_on(queue.send(1)), Err(Closed(1)));
+    }
+
+    #[test]
+    fn dropped_recv_passes_on_wakeup() {
+        let queue = TsQueue::new();
+        let wakers: Vec<_> = (0..2).map(|_| Arc::new(CountingWaker::default())).collect();
+        let mut first = Box::pin(queue.recv());
+        let mut second = Box::pin(queue.recv());
+        let waker = Waker::from(wakers[0].clone());
+        assert!(first
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending());
+        let waker = Waker::from(wakers[1].clone());
+        assert!(second
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending());
+
+        queue.enqueue(1).unwrap();
+        assert_eq!(wakers[0].0.load(Ordering::SeqCst), 1);
+        assert_eq!(wakers[1].0.load(Ordering::SeqCst), 0);
+        drop(first);
+        assert_eq!(wakers[1].0.load(Ordering::SeqCst), 1);
+        assert_eq!(block_on(second), Some(1));
+    }
+}
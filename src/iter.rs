@@ -0,0 +1,205 @@
+use crate::lock::MutexGuard;
+use crate::{DefaultRawMutex, Node, RawMutex, TsQueue};
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::iter::{FromIterator, FusedIterator};
+use core::mem;
+use core::ptr::NonNull;
+
+impl<T, R: RawMutex> TsQueue<T, R> {
+    /// Returns an iterator dequeueing elements until the queue is empty.
+    ///
+    /// The head lock is held until the iterator is dropped, so the yielded
+    /// elements are not interleaved with those of other consumers, which block
+    /// in the meantime. Elements that are not consumed stay in the queue.
+    pub fn drain(&self) -> Drain<'_, T, R> {
+        Drain {
+            queue: self,
+            head: self.lock_head(),
+        }
+    }
+
+    /// Returns an iterator that [`dequeue`](Self::dequeue)s elements until the
+    /// queue is momentarily empty. Does not block.
+    pub fn try_iter(&self) -> TryIter<'_, T, R> {
+        TryIter { queue: self }
+    }
+
+    /// Returns the elements in FIFO order.
+    pub fn into_vec(self) -> Vec<T> {
+        self.into_iter().collect()
+    }
+
+    // Unlinks the front node without locking, which the exclusive borrow
+    // makes unnecessary.
+    fn pop_front_mut(&mut self) -> Option<T> {
+        let head = self.head.get_mut();
+        let next = NonNull::new(*unsafe { head.as_mut() }.next.get_mut())?;
+        let node = unsafe { Box::from_raw(mem::replace(head, next).as_ptr()) };
+        *self.len.get_mut() -= 1;
+        Some(unsafe { node.data.assume_init_read() })
+    }
+}
+
+/// Owning iterator over the elements of a [`TsQueue`] in FIFO order.
+pub struct IntoIter<T, R: RawMutex = DefaultRawMutex> {
+    queue: TsQueue<T, R>,
+}
+
+impl<T, R: RawMutex> IntoIterator for TsQueue<T, R> {
+    type Item = T;
+    type IntoIter = IntoIter<T, R>;
+
+    fn into_iter(self) -> IntoIter<T, R> {
+        IntoIter { queue: self }
+    }
+}
+
+impl<T, R: RawMutex> Iterator for IntoIter<T, R> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.queue.pop_front_mut()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.queue.len();
+        (len, Some(len))
+    }
+}
+
+impl<T, R: RawMutex> ExactSizeIterator for IntoIter<T, R> {}
+
+impl<T, R: RawMutex> FusedIterator for IntoIter<T, R> {}
+
+impl<T, R: RawMutex> FromIterator<T> for TsQueue<T, R> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let queue = Self::default();
+        (&queue).extend(iter);
+        queue
+    }
+}
+
+// Only implemented for the default lock, so that `TsQueue::from` does not
+// need a type annotation.
+impl<T> From<Vec<T>> for TsQueue<T> {
+    fn from(vec: Vec<T>) -> Self {
+        vec.into_iter().collect()
+    }
+}
+
+impl<T> From<VecDeque<T>> for TsQueue<T> {
+    fn from(deque: VecDeque<T>) -> Self {
+        deque.into_iter().collect()
+    }
+}
+
+/// Enqueues the elements with [`TsQueue::enqueue_batch`].
+///
+/// # Panics
+/// Panics if the queue has been closed.
+impl<T, R: RawMutex> Extend<T> for &TsQueue<T, R> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        if self.enqueue_batch(iter).is_err() {
+            panic!("extend on a closed queue");
+        }
+    }
+}
+
+/// See the implementation for `&TsQueue`.
+impl<T, R: RawMutex> Extend<T> for TsQueue<T, R> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        (&*self).extend(iter);
+    }
+}
+
+/// Iterator returned by [`TsQueue::drain`].
+pub struct Drain<'a, T, R: RawMutex = DefaultRawMutex> {
+    queue: &'a TsQueue<T, R>,
+    head: MutexGuard<'a, R, NonNull<Node<T>>>,
+}
+
+impl<T, R: RawMutex> Iterator for Drain<'_, T, R> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let (data, node) = self.queue.unlink_front(&mut self.head)?;
+        self.queue.finish_pop(node);
+        Some(data)
+    }
+}
+
+/// Iterator returned by [`TsQueue::try_iter`].
+pub struct TryIter<'a, T, R: RawMutex = DefaultRawMutex> {
+    queue: &'a TsQueue<T, R>,
+}
+
+impl<T, R: RawMutex> Iterator for TryIter<'_, T, R> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.queue.dequeue().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TsQueue;
+    use std::collections::VecDeque;
+    use std::thread;
+
+    #[test]
+    fn into_iter() {
+        let queue = TsQueue::new();
+        for i in 0..5 {
+            queue.enqueue(i).unwrap();
+        }
+        let mut iter = queue.into_iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.next(), Some(0));
+        assert_eq!(iter.collect::<Vec<_>>(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn drain_and_try_iter() {
+        let queue = TsQueue::new();
+        for i in 0..10 {
+            queue.enqueue(i).unwrap();
+        }
+        assert_eq!(queue.try_iter().take(3).collect::<Vec<_>>(), [0, 1, 2]);
+
+        let mut drain = queue.drain();
+        assert_eq!(drain.next(), Some(3));
+        thread::scope(|s| {
+            // Blocks on the head lock until the drain is dropped.
+            let consumer = s.spawn(|| queue.dequeue());
+            assert_eq!(drain.by_ref().take(3).collect::<Vec<_>>(), [4, 5, 6]);
+            drop(drain);
+            assert_eq!(consumer.join().unwrap(), Ok(7));
+        });
+
+        assert_eq!(queue.drain().collect::<Vec<_>>(), [8, 9]);
+        assert_eq!(queue.try_iter().next(), None);
+    }
+
+    #[test]
+    fn conversions() {
+        let queue: TsQueue<_> = (0..3).collect();
+        (&queue).extend(3..5);
+        assert_eq!(queue.len(), 5);
+        assert_eq!(queue.into_vec(), [0, 1, 2, 3, 4]);
+
+        let mut queue = TsQueue::from(vec![0, 1]);
+        queue.extend(vec![2]);
+        assert_eq!(queue.into_vec(), [0, 1, 2]);
+        let queue: TsQueue<_> = VecDeque::from(vec![0, 1]).into();
+        assert_eq!(queue.into_vec(), [0, 1]);
+
+        #[derive(Default)]
+        struct Holder {
+            queue: TsQueue<i32>,
+        }
+        assert!(Holder::default().queue.is_empty());
+    }
+}
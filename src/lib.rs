@@ -1,121 +1,1122 @@
-use std::ptr;
-use std::ptr::NonNull;
-use std::sync::Mutex;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-pub struct TsQueue<T> {
-    head: Mutex<NonNull<Node<T>>>,
-    tail: Mutex<NonNull<Node<T>>>,
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::mem::{self, ManuallyDrop, MaybeUninit};
+use core::panic::{RefUnwindSafe, UnwindSafe};
+use core::ptr;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+pub use crate::builder::Builder;
+pub use crate::channel::{channel, Receiver, Sender};
+#[cfg(feature = "std")]
+pub use crate::clock::MonotonicClock;
+pub use crate::clock::{Clock, ManualClock};
+pub use crate::deque::TsDeque;
+pub use crate::error::{
+    Closed, DequeueError, DequeueTimeoutError, TryDequeueError, TryEnqueueError,
+};
+pub use crate::future::{RecvFuture, SendFuture};
+pub use crate::iter::{Drain, IntoIter, TryIter};
+#[cfg(feature = "std")]
+pub use crate::lock::StdMutex;
+pub use crate::lock::{DefaultRawMutex, RawMutex, SpinLock};
+#[cfg(feature = "std")]
+pub use crate::lock_free::LockFreeQueue;
+pub use crate::pool::PoolStats;
+pub use crate::priority::{NoKey, TsPriorityQueue};
+pub use crate::queue::Queue;
+pub use crate::segmented::SegmentedTsQueue;
+pub use crate::static_queue::StaticTsQueue;
+
+use crate::flow::FlowControl;
+use crate::lock::{Mutex, MutexGuard};
+use crate::padded::CachePadded;
+use crate::pool::NodePool;
+use crate::waker::WakerSet;
+
+mod builder;
+mod channel;
+mod clock;
+mod deque;
+mod error;
+mod flow;
+mod future;
+mod iter;
+mod lock;
+#[cfg(feature = "std")]
+mod lock_free;
+mod padded;
+mod pool;
+mod priority;
+mod queue;
+mod segmented;
+mod static_queue;
+#[cfg(feature = "futures")]
+mod stream;
+mod waker;
+
+/// Thread-safe FIFO queue guarded by separate head and tail locks.
+///
+/// # Unwind safety
+/// A panic in user code called by the queue, such as the closure passed to
+/// [`peek_with`](Self::peek_with), a waker or the `Drop` of an element, never
+/// leaves the queue in an inconsistent state. It may however poison the
+/// internal locks. All operations keep working on a poisoned queue, only
+/// [`try_enqueue`](Self::try_enqueue) and [`try_dequeue`](Self::try_dequeue)
+/// report it, until [`clear_poison`](Self::clear_poison) is called. Dropping a
+/// queue drops all remaining elements, even if one of them panics. The queue is
+/// therefore [`UnwindSafe`] and [`RefUnwindSafe`], like `std::sync::Mutex`.
+///
+/// There is no separate poison error type: poisoning is reported by the
+/// `Poisoned` variants of [`TryEnqueueError`] and [`TryDequeueError`], which
+/// hand back the rejected element like the other variants. The blocking and
+/// async operations deliberately do not fail on a poisoned queue, since it is
+/// still consistent and failing them would turn one panic into a panic or an
+/// error in every thread using the queue. Callers that want to stop using a
+/// poisoned queue check [`is_poisoned`](Self::is_poisoned).
+///
+/// # Lock backend
+/// The head and tail locks are implemented by the [`RawMutex`] `R`, which
+/// defaults to the blocking `StdMutex`, or to [`SpinLock`] without the `std`
+/// feature. Other backends are selected through [`Builder::lock`].
+pub struct TsQueue<T, R: RawMutex = DefaultRawMutex> {
+    // Consumers and producers work on different cache lines. `len` is
+    // modified by both and gets a line of its own as well.
+    head: CachePadded<Mutex<R, NonNull<Node<T>>>>,
+    tail: CachePadded<Mutex<R, NonNull<Node<T>>>>,
+    // Capacity, closed flag and condition variables, paired with the tail
+    // mutex.
+    flow: FlowControl,
+    // Async counterparts of `not_empty` and `not_full`. Tasks register while
+    // holding the tail lock.
+    recv_wakers: WakerSet,
+    send_wakers: WakerSet,
+    // Number of enqueued elements, exposed through `len`. Incremented under
+    // the tail lock before elements are published, decremented by consumers
+    // after unlinking them.
+    len: CachePadded<AtomicUsize>,
+    // Number of `enqueue_batch` calls waiting for room for their whole batch
+    // in a queue that is not full, see `release_slots`.
+    batch_waiters: AtomicUsize,
+    pool: Option<NodePool<T>>,
 }
 
 // Drop is only needed for the Queue itself and not a single Node.
 // This implementation also avoids a stack overflow.
-impl<T> Drop for TsQueue<T> {
+impl<T, R: RawMutex> Drop for TsQueue<T, R> {
     fn drop(&mut self) {
-        unsafe {
-            let mut x = Box::<Node<T>>::from_raw(self.head.get_mut().unwrap().as_ptr());
-            while let Some(next) = x.next.take() {
-                x = Box::from_raw(next.as_ptr());
-            }
+        let head = *self.head.get_mut();
+        unsafe { free_nodes(Some(head)) };
+    }
+}
+
+// Frees the list of nodes starting at `node` and drops their data. If dropping
+// an element panics, the rest of the list is still freed while unwinding.
+//
+// Safety: the nodes must be exclusively owned by the caller.
+unsafe fn free_nodes<T>(mut node: Option<NonNull<Node<T>>>) {
+    struct Rest<T>(Option<NonNull<Node<T>>>);
+
+    impl<T> Drop for Rest<T> {
+        fn drop(&mut self) {
+            unsafe { free_nodes(self.0) };
+        }
+    }
+
+    while let Some(current) = node {
+        let mut current = Box::from_raw(current.as_ptr());
+        let rest = Rest(current.take_next());
+        if rest.0.is_some() {
+            current.data.assume_init_drop();
         }
+        drop(current);
+        node = rest.0;
+        mem::forget(rest);
     }
 }
 
-unsafe impl<T: Send> Send for TsQueue<T> {}
-unsafe impl<T: Send> Sync for TsQueue<T> {}
+unsafe impl<T: Send, R: RawMutex> Send for TsQueue<T, R> {}
+unsafe impl<T: Send, R: RawMutex> Sync for TsQueue<T, R> {}
 
+impl<T, R: RawMutex> UnwindSafe for TsQueue<T, R> {}
+impl<T, R: RawMutex> RefUnwindSafe for TsQueue<T, R> {}
+
+// `next` is published with release ordering once `data` has been written, so
+// consumers detect a non-empty queue from the head node without taking the
+// tail lock. Nodes that are not linked into a queue are accessed through
+// `take_next` and `set_next`.
+//
+// `data` is initialized exactly while `next` is set, i.e. for the nodes from
+// head to tail and in chains, until a consumer moves it out after unlinking
+// the node. Without a discriminant the node stays as small as possible, and
+// `next` comes first since it is the field both ends access.
+#[repr(C)]
 struct Node<T> {
-    data: Option<T>,
-    next: Option<NonNull<Node<T>>>,
+    next: AtomicPtr<Node<T>>,
+    data: MaybeUninit<T>,
 }
 
 impl<T> Node<T> {
     fn new() -> NonNull<Self> {
         Box::leak(Box::new(Self {
-            data: None,
-            next: None,
+            next: AtomicPtr::new(ptr::null_mut()),
+            data: MaybeUninit::uninit(),
         }))
         .into()
     }
+
+    fn take_next(&mut self) -> Option<NonNull<Self>> {
+        NonNull::new(mem::replace(self.next.get_mut(), ptr::null_mut()))
+    }
+
+    fn set_next(&mut self, next: Option<NonNull<Self>>) {
+        *self.next.get_mut() = next.map_or(ptr::null_mut(), NonNull::as_ptr);
+    }
+}
+
+// A run of elements prepared outside of the tail lock. `data` is stored in the
+// current tail node when the chain is linked, the following elements live in
+// the nodes from `first` to `last`, and `last` is left empty to become the new
+// dummy tail.
+struct Chain<T> {
+    data: T,
+    first: NonNull<Node<T>>,
+    last: NonNull<Node<T>>,
+    len: usize,
+}
+
+impl<T> Chain<T> {
+    fn new(
+        items: impl IntoIterator<Item = T>,
+        mut alloc: impl FnMut() -> NonNull<Node<T>>,
+    ) -> Option<Self> {
+        let mut items = items.into_iter();
+        let data = items.next()?;
+        let node = alloc();
+        let mut chain = Self {
+            data,
+            first: node,
+            last: node,
+            len: 1,
+        };
+        for data in items {
+            let node = alloc();
+            unsafe {
+                chain.last.as_mut().data = MaybeUninit::new(data);
+                chain.last.as_mut().set_next(Some(node));
+            }
+            chain.last = node;
+            chain.len += 1;
+        }
+        Some(chain)
+    }
+
+    // Splits the chain after its first `n` elements, `0 < n < self.len`.
+    fn split_off(&mut self, n: usize) -> Self {
+        debug_assert!(0 < n && n < self.len);
+        let mut node = self.first;
+        for _ in 1..n {
+            node =
+                NonNull::new(*unsafe { node.as_mut() }.next.get_mut()).expect("chain ended early");
+        }
+        // The n-th node becomes our empty last node, its data heads the rest.
+        let node_mut = unsafe { node.as_mut() };
+        let rest = Self {
+            data: unsafe { node_mut.data.assume_init_read() },
+            first: node_mut.take_next().expect("chain ended early"),
+            last: self.last,
+            len: self.len - n,
+        };
+        self.last = node;
+        self.len = n;
+        rest
+    }
+
+    fn into_parts(self) -> (T, NonNull<Node<T>>, NonNull<Node<T>>, usize) {
+        let chain = ManuallyDrop::new(self);
+        (
+            unsafe { ptr::read(&chain.data) },
+            chain.first,
+            chain.last,
+            chain.len,
+        )
+    }
+
+    fn into_vec(self) -> Vec<T> {
+        let (data, first, _, len) = self.into_parts();
+        let mut out = Vec::with_capacity(len);
+        out.push(data);
+        let mut node = Some(first);
+        while let Some(next) = node {
+            let mut node_box = unsafe { Box::from_raw(next.as_ptr()) };
+            node = node_box.take_next();
+            if node.is_some() {
+                out.push(unsafe { node_box.data.assume_init_read() });
+            }
+        }
+        out
+    }
+}
+
+// Frees the nodes if the chain is dropped without being linked, e.g. when the
+// iterator building it panics.
+impl<T> Drop for Chain<T> {
+    fn drop(&mut self) {
+        unsafe { free_nodes(Some(self.first)) };
+    }
 }
 
 impl<T> TsQueue<T> {
     pub fn new() -> Self {
+        Self::with_config(None, None)
+    }
+
+    /// Creates a queue holding at most `capacity` elements. Enqueueing into a
+    /// full bounded queue blocks until a consumer makes room.
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero.
+    pub fn bounded(capacity: usize) -> Self {
+        Self::builder().capacity(capacity).build()
+    }
+
+    pub fn builder() -> Builder<T> {
+        Builder::new()
+    }
+}
+
+impl<T, R: RawMutex> Default for TsQueue<T, R> {
+    fn default() -> Self {
+        Self::with_config(None, None)
+    }
+}
+
+impl<T, R: RawMutex> TsQueue<T, R> {
+    fn with_config(capacity: Option<usize>, pool: Option<NodePool<T>>) -> Self {
         let dummy = Node::new();
-        let tail = Mutex::new(dummy);
-        let head = Mutex::new(dummy);
-        Self { head, tail }
+        let tail = CachePadded::new(Mutex::new(dummy));
+        let head = CachePadded::new(Mutex::new(dummy));
+        Self {
+            head,
+            tail,
+            flow: FlowControl::new(capacity),
+            recv_wakers: WakerSet::new(),
+            send_wakers: WakerSet::new(),
+            len: CachePadded::new(AtomicUsize::new(0)),
+            batch_waiters: AtomicUsize::new(0),
+            pool,
+        }
+    }
+
+    /// Returns the counters of the node pool, or `None` if the queue was built
+    /// without one.
+    pub fn pool_stats(&self) -> Option<PoolStats> {
+        self.pool.as_ref().map(NodePool::stats)
+    }
+
+    fn alloc_node(&self) -> NonNull<Node<T>> {
+        match &self.pool {
+            Some(pool) => pool.alloc(),
+            None => Node::new(),
+        }
+    }
+
+    // Safety: `node` must be unlinked and exclusively owned by the caller, and
+    // its data must have been taken.
+    unsafe fn free_node(&self, node: NonNull<Node<T>>) {
+        match &self.pool {
+            Some(pool) => pool.free(node),
+            None => drop(Box::from_raw(node.as_ptr())),
+        }
+    }
+
+    // Frees a node allocated for an enqueue that was rejected. Nodes are
+    // allocated before taking the tail lock, so a full or closed queue is only
+    // detected afterwards.
+    //
+    // Safety: `node` must come from `alloc_node` and never have been linked.
+    unsafe fn free_unused_node(&self, node: NonNull<Node<T>>) {
+        match &self.pool {
+            Some(pool) => pool.free_unused(node),
+            None => drop(Box::from_raw(node.as_ptr())),
+        }
+    }
+
+    /// Returns the capacity of a bounded queue or `None` if it is unbounded.
+    pub fn capacity(&self) -> Option<usize> {
+        self.flow.capacity()
+    }
+
+    /// Returns the number of elements in the queue.
+    ///
+    /// The count is read from a counter maintained by the enqueue and dequeue
+    /// operations instead of traversing the nodes. Under concurrent access it
+    /// is only a snapshot, but it never exceeds the capacity of a bounded
+    /// queue.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.flow.is_full(self.len())
+    }
+
+    /// Enqueues `data`, blocking while a bounded queue is full. Fails if the
+    /// queue has been closed.
+    pub fn enqueue(&self, data: T) -> Result<(), Closed<T>> {
+        let node = self.alloc_node();
+        let tail = match self.flow.wait_for_room(self.lock_tail(), || self.len()) {
+            Some(tail) => tail,
+            None => {
+                unsafe { self.free_unused_node(node) };
+                return Err(Closed(data));
+            }
+        };
+        self.push(tail, node, data);
+        Ok(())
+    }
+
+    /// Enqueues `data` unless the queue is full, closed or poisoned, in which
+    /// case it is handed back inside the error.
+    pub fn try_enqueue(&self, data: T) -> Result<(), TryEnqueueError<T>> {
+        if self.is_poisoned() {
+            return Err(TryEnqueueError::Poisoned(data));
+        }
+        let node = self.alloc_node();
+        let tail = self.lock_tail();
+        match self.flow.check_room(data, self.len()) {
+            Ok(data) => {
+                self.push(tail, node, data);
+                Ok(())
+            }
+            Err(err) => {
+                drop(tail);
+                unsafe { self.free_unused_node(node) };
+                Err(err)
+            }
+        }
     }
 
-    pub fn enqueue(&self, data: T) {
-        let node = Node::new();
-        let new_tail = node;
-        let mut tail = self.tail.lock().expect("Unable to lock tail mutex");
+    /// Enqueues all `items` such that they are dequeued contiguously and in
+    /// order, blocking while a bounded queue is full.
+    ///
+    /// The nodes are allocated and linked before the tail lock is taken, the
+    /// whole chain is then appended with a single lock acquisition. On a
+    /// bounded queue this waits until there is room for the whole batch.
+    ///
+    /// The only exception is a batch larger than the capacity, which can never
+    /// fit at once. It is appended in runs as space becomes available, and
+    /// other producers may interleave between those runs.
+    ///
+    /// If the queue is closed, the elements that have not been enqueued yet
+    /// are returned in the error.
+    pub fn enqueue_batch(&self, items: impl IntoIterator<Item = T>) -> Result<(), Closed<Vec<T>>> {
+        let mut chain = match Chain::new(items, || self.alloc_node()) {
+            Some(chain) => chain,
+            None => return Ok(()),
+        };
+        let oversized = self
+            .flow
+            .capacity()
+            .is_some_and(|capacity| chain.len > capacity);
+        let mut tail = self.lock_tail();
+        loop {
+            if self.flow.is_closed() {
+                drop(tail);
+                return Err(Closed(chain.into_vec()));
+            }
+            let free = self.free_slots();
+            if free >= chain.len {
+                self.link(tail, chain);
+                return Ok(());
+            }
+            if oversized && free > 0 {
+                let rest = chain.split_off(free);
+                self.link(tail, chain);
+                chain = rest;
+                tail = self.lock_tail();
+            } else {
+                self.batch_waiters.fetch_add(1, Ordering::SeqCst);
+                // Consumers that freed slots before the registration became
+                // visible did not notify, so look again before waiting.
+                if self.free_slots() == free {
+                    tail = self.flow.not_full.wait(tail);
+                }
+                self.batch_waiters.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    fn free_slots(&self) -> usize {
+        self.flow.free_slots(self.len.load(Ordering::SeqCst))
+    }
+
+    // Stores `data` in the current tail node and appends `node` as the new
+    // dummy tail. Releases the tail lock before waking a consumer.
+    fn push(&self, tail: MutexGuard<'_, R, NonNull<Node<T>>>, node: NonNull<Node<T>>, data: T) {
+        self.link(
+            tail,
+            Chain {
+                data,
+                first: node,
+                last: node,
+                len: 1,
+            },
+        );
+    }
+
+    fn link(&self, mut tail: MutexGuard<'_, R, NonNull<Node<T>>>, chain: Chain<T>) {
+        let (data, first, last, len) = chain.into_parts();
+        // Counted before publishing, so consumers never decrement below zero.
+        self.len.fetch_add(len, Ordering::Relaxed);
+        // A consumer may concurrently load `next` of the tail node, so only
+        // the fields are accessed and no reference to the node is created.
         unsafe {
-            tail.as_mut().data = Some(data);
-            tail.as_mut().next = Some(node);
+            let node = tail.as_ptr();
+            (*node).data = MaybeUninit::new(data);
+            (*node).next.store(first.as_ptr(), Ordering::Release);
+        }
+        *tail = last;
+        drop(tail);
+        if len == 1 {
+            self.flow.not_empty.notify_one();
+            self.recv_wakers.notify_one();
+        } else {
+            self.flow.not_empty.notify_all();
+            self.recv_wakers.notify_all();
+        }
+    }
+
+    /// Dequeues the front element without blocking. Elements enqueued before
+    /// the queue was closed can still be dequeued, after that
+    /// `DequeueError::Closed` is returned.
+    ///
+    /// Only takes the head lock, so it does not contend with producers.
+    pub fn dequeue(&self) -> Result<T, DequeueError> {
+        self.flow.dequeue(|| self.pop())
+    }
+
+    /// Like [`dequeue`](Self::dequeue), but fails if the queue has been
+    /// poisoned.
+    pub fn try_dequeue(&self) -> Result<T, TryDequeueError> {
+        if self.is_poisoned() {
+            return Err(TryDequeueError::Poisoned);
         }
-        *tail = new_tail;
+        self.dequeue().map_err(Into::into)
+    }
+
+    /// Dequeues the front element, blocking the current thread until one is
+    /// available. Returns `None` once the queue is closed and drained.
+    pub fn dequeue_blocking(&self) -> Option<T> {
+        self.flow.dequeue_blocking(|| self.pop_or_tail_guard())
+    }
+
+    /// Dequeues the front element, blocking for at most `timeout` until one is
+    /// available.
+    #[cfg(feature = "std")]
+    pub fn dequeue_timeout(&self, timeout: Duration) -> Result<T, DequeueTimeoutError> {
+        self.flow
+            .dequeue_timeout(timeout, || self.pop_or_tail_guard())
     }
 
-    pub fn dequeue(&self) -> Option<T> {
-        let mut head = self.head.lock().expect("Unable to lock head");
-        if ptr::eq(head.as_ptr(), self.get_tail_ptr()) {
-            return None;
+    /// Dequeues the front element, blocking until one is available or the
+    /// `deadline` has passed.
+    #[cfg(feature = "std")]
+    pub fn dequeue_deadline(&self, deadline: Instant) -> Result<T, DequeueTimeoutError> {
+        self.flow
+            .dequeue_deadline(deadline, || self.pop_or_tail_guard())
+    }
+
+    /// Dequeues up to `max` elements into `out` and returns how many were
+    /// dequeued.
+    ///
+    /// The elements are unlinked under a single acquisition of the head lock.
+    /// Their nodes are freed after the lock has been released.
+    pub fn dequeue_batch(&self, max: usize, out: &mut Vec<T>) -> usize {
+        if max == 0 {
+            return 0;
+        }
+        let mut head = self.lock_head();
+        let first = *head;
+        let mut n = 0;
+        while n < max {
+            match self.next_of_head(&head) {
+                Some(next) => *head = next,
+                None => break,
+            }
+            n += 1;
         }
-        let mut head_box = unsafe { Box::<Node<T>>::from_raw(head.as_ptr()) };
-        let new_head = head_box
-            .next
-            .take()
-            .expect("head != tail but head.next is empty");
-        *head = new_head;
-        head_box.data.take()
+        drop(head);
+        if n == 0 {
+            return 0;
+        }
+        self.release_slots(n);
+
+        out.reserve(n);
+        let mut node = first;
+        for _ in 0..n {
+            let node_mut = unsafe { &mut *node.as_ptr() };
+            out.push(unsafe { node_mut.data.assume_init_read() });
+            let next = node_mut.take_next();
+            unsafe { self.free_node(node) };
+            node = match next {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        n
+    }
+
+    /// Dequeues all elements currently in the queue. See
+    /// [`dequeue_batch`](Self::dequeue_batch).
+    pub fn drain_all(&self) -> Vec<T> {
+        let mut out = Vec::new();
+        self.dequeue_batch(usize::MAX, &mut out);
+        out
+    }
+
+    /// Runs `f` on the front element without removing it and returns its
+    /// result, or `None` if the queue is empty.
+    ///
+    /// The head lock is held while `f` runs, so the element cannot be
+    /// dequeued concurrently, but other consumers are blocked until `f`
+    /// returns.
+    pub fn peek_with<U>(&self, f: impl FnOnce(&T) -> U) -> Option<U> {
+        let head = self.lock_head();
+        self.next_of_head(&head)?;
+        // Producers do not touch the data of a node after publishing `next`.
+        let data = unsafe { (*head.as_ptr()).data.assume_init_ref() };
+        Some(f(data))
+    }
+
+    /// Closes the queue. Subsequent enqueues fail, while the remaining elements
+    /// can still be dequeued. Wakes up all blocked producers and consumers.
+    pub fn close(&self) {
+        self.flow.close(self.lock_tail());
+        self.recv_wakers.notify_all();
+        self.send_wakers.notify_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.flow.is_closed()
+    }
+
+    /// Returns `true` if a thread panicked while holding one of the queue's
+    /// locks.
+    pub fn is_poisoned(&self) -> bool {
+        self.head.is_poisoned() || self.tail.is_poisoned()
+    }
+
+    /// Clears the poisoned state so that the `try_` operations succeed again.
+    pub fn clear_poison(&self) {
+        self.head.clear_poison();
+        self.tail.clear_poison();
+    }
+
+    // A poisoned lock is recovered instead of propagating the panic. Panics
+    // can only originate in user code that runs while the queue is
+    // consistent, so the protected state is always valid.
+    fn lock_head(&self) -> MutexGuard<'_, R, NonNull<Node<T>>> {
+        self.head.lock()
+    }
+
+    fn lock_tail(&self) -> MutexGuard<'_, R, NonNull<Node<T>>> {
+        self.tail.lock()
     }
 
-    fn get_tail_ptr(&self) -> *const Node<T> {
-        self.tail.lock().expect("Unable to lock tail").as_ptr()
+    // Returns the successor of the head node, or `None` if the queue is empty.
+    // Once `next` is set, the data of the head node is complete.
+    fn next_of_head(&self, head: &NonNull<Node<T>>) -> Option<NonNull<Node<T>>> {
+        NonNull::new(unsafe { (*head.as_ptr()).next.load(Ordering::Acquire) })
+    }
+
+    // Unlinks the front node and returns its data together with the node, which
+    // the caller passes to `finish_pop` after releasing the head lock.
+    fn unlink_front(&self, head: &mut NonNull<Node<T>>) -> Option<(T, NonNull<Node<T>>)> {
+        let old_head = *head;
+        *head = self.next_of_head(head)?;
+        let data = unsafe { (*old_head.as_ptr()).data.assume_init_read() };
+        Some((data, old_head))
+    }
+
+    fn finish_pop(&self, node: NonNull<Node<T>>) {
+        self.release_slots(1);
+        unsafe { self.free_node(node) };
+    }
+
+    // Pops the front element while only holding the head lock.
+    fn pop(&self) -> Option<T> {
+        let mut head = self.lock_head();
+        let (data, node) = self.unlink_front(&mut head)?;
+        drop(head);
+        self.finish_pop(node);
+        Some(data)
+    }
+
+    // Pops the front element or, if the queue is empty, returns the still held
+    // tail guard so that callers can wait on `not_empty` without missing a
+    // concurrent `enqueue`.
+    fn pop_or_tail_guard(&self) -> Result<T, MutexGuard<'_, R, NonNull<Node<T>>>> {
+        if let Some(data) = self.pop() {
+            return Ok(data);
+        }
+        let mut head = self.lock_head();
+        // Producers publish while holding the tail lock, so a queue that is
+        // empty under it stays empty until the guard is released.
+        let tail = self.lock_tail();
+        match self.unlink_front(&mut head) {
+            Some((data, node)) => {
+                drop(tail);
+                drop(head);
+                self.finish_pop(node);
+                Ok(data)
+            }
+            None => Err(tail),
+        }
+    }
+
+    // Accounts for `n` dequeued elements. The tail lock is only taken if the
+    // queue was full or a batch is waiting for more room: a producer that saw
+    // too little room holds the lock until it waits on `not_full`, so the
+    // notification cannot get lost.
+    fn release_slots(&self, n: usize) {
+        let was_full = Some(self.len.fetch_sub(n, Ordering::SeqCst)) == self.flow.capacity();
+        if was_full || self.batch_waiters.load(Ordering::SeqCst) > 0 {
+            drop(self.lock_tail());
+            self.flow.not_full.notify_all();
+            self.send_wakers.notify_all();
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::TsQueue;
+    use crate::{Closed, DequeueError, RawMutex, SpinLock, TryEnqueueError, TsQueue};
+    #[cfg(feature = "std")]
+    use crate::{DequeueTimeoutError, TryDequeueError};
+    use std::collections::VecDeque;
+    use std::panic;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+    #[cfg(feature = "std")]
+    use std::time::Instant;
+
+    // Enqueues `0..10000` through `enqueue` while another thread collects them
+    // through `dequeue`, which is retried while it returns `None`, and checks
+    // that they arrive in order.
+    pub(crate) fn roundtrip(
+        mut enqueue: impl FnMut(i32),
+        mut dequeue: impl FnMut() -> Option<i32> + Send,
+    ) {
+        const N: i32 = 10000;
+        let data = thread::scope(|s| {
+            let consumer = s.spawn(move || {
+                let mut data = Vec::with_capacity(N as usize);
+                while data.len() < N as usize {
+                    match dequeue() {
+                        Some(i) => data.push(i),
+                        None => thread::yield_now(),
+                    }
+                }
+                data
+            });
+            for i in 0..N {
+                enqueue(i);
+            }
+            consumer.join().unwrap()
+        });
+        assert_eq!(data, (0..N).collect::<Vec<_>>());
+    }
 
     #[test]
     fn single_threaded() {
         let queue: TsQueue<i32> = TsQueue::new();
-        let data_expected: Vec<_> = (0..5).into_iter().collect();
+        let data_expected: Vec<_> = (0..5).collect();
         let mut data = data_expected.clone();
+        assert!(queue.is_empty());
         for i in data.drain(..) {
-            queue.enqueue(i);
+            queue.enqueue(i).unwrap();
         }
-        while let Some(i) = queue.dequeue() {
+        assert_eq!(queue.len(), 5);
+        while let Ok(i) = queue.dequeue() {
             data.push(i);
         }
         assert_eq!(data_expected, data);
+        assert!(queue.is_empty());
     }
 
     #[test]
     fn multi_threaded() {
         let queue = TsQueue::new();
-        let data_expected: Vec<_> = (0..=9999).into_iter().collect();
-        let mut data_recv = Vec::with_capacity(10000);
+        roundtrip(|i| queue.enqueue(i).unwrap(), || queue.dequeue().ok());
+    }
+
+    #[test]
+    fn blocking_dequeue() {
+        let queue = TsQueue::new();
+        roundtrip(|i| queue.enqueue(i).unwrap(), || queue.dequeue_blocking());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn timed_dequeue() {
+        let queue = TsQueue::new();
+        let timeout = Duration::from_millis(20);
+        let start = Instant::now();
+        assert_eq!(
+            queue.dequeue_timeout(timeout),
+            Err(DequeueTimeoutError::Timeout)
+        );
+        assert!(start.elapsed() >= timeout);
+        assert_eq!(
+            queue.dequeue_deadline(Instant::now()),
+            Err(DequeueTimeoutError::Timeout)
+        );
+
+        thread::scope(|s| {
+            let consumer = s.spawn(|| queue.dequeue_timeout(Duration::from_secs(10)));
+            queue.enqueue(42).unwrap();
+            assert_eq!(consumer.join().unwrap(), Ok(42));
+        });
+    }
 
-        rayon::join(
+    #[test]
+    fn bounded() {
+        let queue = TsQueue::bounded(2);
+        assert_eq!(queue.capacity(), Some(2));
+        assert_eq!(queue.try_enqueue(1), Ok(()));
+        assert!(!queue.is_full());
+        assert_eq!(queue.try_enqueue(2), Ok(()));
+        assert!(queue.is_full());
+        assert_eq!(queue.try_enqueue(3), Err(TryEnqueueError::Full(3)));
+        assert_eq!(queue.dequeue(), Ok(1));
+        assert_eq!(queue.try_enqueue(3), Ok(()));
+        assert_eq!(queue.dequeue(), Ok(2));
+        assert_eq!(queue.dequeue(), Ok(3));
+        assert_eq!(queue.dequeue(), Err(DequeueError::Empty));
+    }
+
+    #[test]
+    fn bounded_backpressure() {
+        let queue = TsQueue::bounded(4);
+        roundtrip(
+            |i| queue.enqueue(i).unwrap(),
             || {
-                for i in &data_expected {
-                    queue.enqueue(*i);
+                assert!(queue.len() <= 4);
+                queue.dequeue_blocking()
+            },
+        );
+    }
+
+    fn blocking_with_lock<R: RawMutex>() {
+        let queue = TsQueue::builder().capacity(4).lock::<R>().build();
+        roundtrip(|i| queue.enqueue(i).unwrap(), || queue.dequeue_blocking());
+    }
+
+    #[test]
+    fn spin_lock() {
+        blocking_with_lock::<SpinLock>();
+    }
+
+    #[cfg(feature = "parking_lot")]
+    #[test]
+    fn parking_lot() {
+        blocking_with_lock::<parking_lot::RawMutex>();
+    }
+
+    #[test]
+    fn close() {
+        let queue = TsQueue::new();
+        queue.enqueue(1).unwrap();
+        queue.enqueue(2).unwrap();
+        assert!(!queue.is_closed());
+        queue.close();
+        assert!(queue.is_closed());
+        assert_eq!(queue.enqueue(3).unwrap_err().into_inner(), 3);
+        assert_eq!(queue.try_enqueue(3), Err(TryEnqueueError::Closed(3)));
+        assert_eq!(queue.dequeue(), Ok(1));
+        assert_eq!(queue.dequeue_blocking(), Some(2));
+        assert_eq!(queue.dequeue(), Err(DequeueError::Closed));
+        assert_eq!(queue.dequeue_blocking(), None);
+        #[cfg(feature = "std")]
+        assert_eq!(
+            queue.dequeue_timeout(Duration::from_secs(10)),
+            Err(DequeueTimeoutError::Closed)
+        );
+    }
+
+    #[test]
+    fn close_wakes_blocked_threads() {
+        let queue = TsQueue::<i32>::bounded(1);
+        thread::scope(|s| {
+            let consumer = s.spawn(|| queue.dequeue_blocking());
+            thread::sleep(Duration::from_millis(10));
+            queue.close();
+            assert_eq!(consumer.join().unwrap(), None);
+        });
+
+        let queue = TsQueue::bounded(1);
+        queue.enqueue(1).unwrap();
+        thread::scope(|s| {
+            let producer = s.spawn(|| queue.enqueue(2));
+            thread::sleep(Duration::from_millis(10));
+            queue.close();
+            assert_eq!(producer.join().unwrap(), Err(Closed(2)));
+        });
+    }
+
+    #[test]
+    fn peek_with() {
+        let queue = TsQueue::new();
+        assert_eq!(queue.peek_with(|i: &i32| *i), None);
+        queue.enqueue(1).unwrap();
+        queue.enqueue(2).unwrap();
+        assert_eq!(queue.peek_with(|i| i * 10), Some(10));
+        assert_eq!(queue.peek_with(|i| i * 10), Some(10));
+        assert_eq!(queue.dequeue(), Ok(1));
+        assert_eq!(queue.peek_with(|i| i * 10), Some(20));
+        assert_eq!(queue.dequeue(), Ok(2));
+        assert_eq!(queue.peek_with(|i| i * 10), None);
+    }
+
+    #[test]
+    fn dequeue_without_tail_lock() {
+        let queue = TsQueue::new();
+        queue.enqueue(1).unwrap();
+        queue.enqueue(2).unwrap();
+        let tail = queue.tail.lock();
+        assert_eq!(queue.dequeue(), Ok(1));
+        assert_eq!(queue.peek_with(|i| *i), Some(2));
+        assert_eq!(queue.drain_all(), [2]);
+        assert_eq!(queue.dequeue(), Err(DequeueError::Empty));
+        drop(tail);
+    }
+
+    #[test]
+    fn dequeue_batch() {
+        let queue = TsQueue::new();
+        for i in 0..10 {
+            queue.enqueue(i).unwrap();
+        }
+        let mut out = vec![];
+        assert_eq!(queue.dequeue_batch(0, &mut out), 0);
+        assert_eq!(queue.dequeue_batch(4, &mut out), 4);
+        assert_eq!(out, [0, 1, 2, 3]);
+        assert_eq!(queue.len(), 6);
+        assert_eq!(queue.dequeue_batch(4, &mut out), 4);
+        assert_eq!(queue.drain_all(), [8, 9]);
+        assert_eq!(queue.dequeue_batch(4, &mut out), 0);
+        assert_eq!(out, (0..8).collect::<Vec<_>>());
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn batch_dequeue_wakes_producers() {
+        let queue = TsQueue::bounded(8);
+        let mut batch = VecDeque::new();
+        roundtrip(
+            |i| queue.enqueue(i).unwrap(),
+            || {
+                if batch.is_empty() {
+                    let mut out = vec![];
+                    queue.dequeue_batch(5, &mut out);
+                    batch.extend(out);
                 }
+                batch.pop_front()
             },
-            || loop {
-                if let Some(i) = queue.dequeue() {
-                    data_recv.push(i);
-                    if i == 9999 {
-                        break;
+        );
+    }
+
+    #[test]
+    fn enqueue_batch() {
+        let queue = TsQueue::new();
+        queue.enqueue_batch(Vec::new()).unwrap();
+        assert!(queue.is_empty());
+        queue.enqueue(0).unwrap();
+        queue.enqueue_batch(1..5).unwrap();
+        queue.enqueue_batch(Some(5)).unwrap();
+        assert_eq!(queue.len(), 6);
+        assert_eq!(queue.drain_all(), (0..6).collect::<Vec<_>>());
+
+        queue.close();
+        assert_eq!(queue.enqueue_batch(0..3), Err(Closed(vec![0, 1, 2])));
+    }
+
+    #[test]
+    fn enqueue_batch_is_contiguous() {
+        let queue = TsQueue::new();
+        thread::scope(|s| {
+            for p in 0..4 {
+                let queue = &queue;
+                s.spawn(move || {
+                    for b in 0..100 {
+                        let start = (p * 100 + b) * 10;
+                        queue.enqueue_batch(start..start + 10).unwrap();
                     }
+                });
+            }
+        });
+        let data = queue.drain_all();
+        assert_eq!(data.len(), 4000);
+        for batch in data.chunks(10) {
+            assert_eq!(batch[0] % 10, 0);
+            assert!(batch.windows(2).all(|w| w[1] == w[0] + 1));
+        }
+    }
+
+    #[test]
+    fn bounded_enqueue_batch_is_contiguous() {
+        let queue = TsQueue::bounded(16);
+        let data = thread::scope(|s| {
+            for p in 0..4 {
+                let queue = &queue;
+                s.spawn(move || {
+                    for b in 0..100 {
+                        let start = (p * 100 + b) * 10;
+                        queue.enqueue_batch(start..start + 10).unwrap();
+                    }
+                });
+            }
+            (0..4000)
+                .map(|_| queue.dequeue_blocking().unwrap())
+                .collect::<Vec<_>>()
+        });
+        for batch in data.chunks(10) {
+            assert_eq!(batch[0] % 10, 0);
+            assert!(batch.windows(2).all(|w| w[1] == w[0] + 1));
+        }
+    }
+
+    #[test]
+    fn bounded_enqueue_batch() {
+        // Batches larger than the capacity are split.
+        let queue = TsQueue::bounded(3);
+        let mut batch = vec![];
+        roundtrip(
+            |i| {
+                batch.push(i);
+                if batch.len() == 8 {
+                    queue.enqueue_batch(batch.drain(..)).unwrap();
                 }
             },
+            || {
+                assert!(queue.len() <= 3);
+                queue.dequeue_blocking()
+            },
         );
+    }
+
+    #[test]
+    fn node_pool() {
+        let queue = TsQueue::builder().node_pool(2).build();
+        assert_eq!(TsQueue::<i32>::new().pool_stats(), None);
+        for i in 0..4 {
+            queue.enqueue(i).unwrap();
+        }
+        assert_eq!(queue.drain_all(), [0, 1, 2, 3]);
+        let stats = queue.pool_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses), (0, 4));
+        assert_eq!((stats.recycled, stats.discarded), (2, 2));
+
+        queue.enqueue_batch(0..3).unwrap();
+        assert_eq!(queue.dequeue(), Ok(0));
+        let stats = queue.pool_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses), (2, 5));
+        assert_eq!((stats.recycled, stats.discarded), (3, 2));
+        assert_eq!(stats.hit_rate(), 2.0 / 7.0);
+    }
+
+    #[test]
+    fn rejected_enqueues_return_nodes_unused() {
+        let queue = TsQueue::builder().capacity(1).node_pool(2).build();
+        queue.enqueue(0).unwrap();
+        for i in 1..4 {
+            assert_eq!(queue.try_enqueue(i), Err(TryEnqueueError::Full(i)));
+        }
+        queue.close();
+        assert_eq!(queue.enqueue(4), Err(Closed(4)));
+        let stats = queue.pool_stats().unwrap();
+        // The node given back by the first rejected enqueue is reused.
+        assert_eq!((stats.hits, stats.misses), (3, 2));
+        assert_eq!((stats.recycled, stats.discarded), (0, 0));
+        assert_eq!(stats.returned_unused, 4);
+    }
+
+    #[test]
+    fn bounded_node_pool() {
+        let queue = TsQueue::builder().capacity(4).node_pool(8).build();
+        roundtrip(|i| queue.enqueue(i).unwrap(), || queue.dequeue_blocking());
+        let stats = queue.pool_stats().unwrap();
+        assert_eq!(stats.hits + stats.misses, 10000);
+        assert!(stats.misses <= 10);
+    }
+
+    // Mutexes are only poisoned with the `std` feature.
+    #[cfg(feature = "std")]
+    #[test]
+    fn poisoned_queue() {
+        let queue = TsQueue::new();
+        queue.enqueue(1).unwrap();
+        queue.enqueue(2).unwrap();
+        let res = panic::catch_unwind(|| queue.peek_with(|_| panic!()));
+        assert!(res.is_err());
+        assert!(queue.is_poisoned());
+
+        assert_eq!(queue.try_dequeue(), Err(TryDequeueError::Poisoned));
+        assert_eq!(queue.try_enqueue(3), Err(TryEnqueueError::Poisoned(3)));
+        // The regular operations recover from the poisoned locks.
+        assert_eq!(queue.dequeue(), Ok(1));
+        queue.enqueue(3).unwrap();
+
+        queue.clear_poison();
+        assert!(!queue.is_poisoned());
+        assert_eq!(queue.try_dequeue(), Ok(2));
+        assert_eq!(queue.try_dequeue(), Ok(3));
+        assert_eq!(queue.try_dequeue(), Err(TryDequeueError::Empty));
+    }
+
+    #[test]
+    fn drop_panics_in_element() {
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        struct PanicOnDrop(bool);
+
+        impl Drop for PanicOnDrop {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::SeqCst);
+                if self.0 {
+                    panic!("element panicked in drop");
+                }
+            }
+        }
+
+        let queue = TsQueue::new();
+        for i in 0..5 {
+            queue.enqueue(PanicOnDrop(i == 1)).unwrap();
+        }
+        // Dropping a dequeued element happens outside of the queue.
+        drop(queue.dequeue().unwrap());
+        let res = panic::catch_unwind(|| drop(queue.dequeue().unwrap()));
+        assert!(res.is_err());
+        assert!(!queue.is_poisoned());
+        assert_eq!(queue.len(), 3);
 
-        assert_eq!(data_expected, data_recv);
+        queue.enqueue(PanicOnDrop(true)).unwrap();
+        queue.enqueue(PanicOnDrop(false)).unwrap();
+        let res = panic::catch_unwind(|| drop(queue));
+        assert!(res.is_err());
+        // The elements behind the panicking one are dropped as well.
+        assert_eq!(DROPPED.load(Ordering::SeqCst), 7);
     }
 }
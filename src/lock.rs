@@ -0,0 +1,478 @@
+use core::cell::UnsafeCell;
+use core::hint;
+use core::marker::PhantomData;
+#[cfg(feature = "std")]
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+/// Raw lock guarding the head and the tail of a [`TsQueue`](crate::TsQueue).
+///
+/// The queue adds poisoning and condition variables on top, so an
+/// implementation only has to provide mutual exclusion. Besides the blocking
+/// [`StdMutex`], the crate provides the spinning [`SpinLock`] and, with the
+/// `parking_lot` feature, an implementation for `parking_lot::RawMutex`.
+///
+/// # Safety
+/// Implementations must guarantee that at most one thread holds the lock at a
+/// time. `lock` must synchronize with the preceding `unlock`.
+pub unsafe trait RawMutex: Send + Sync {
+    /// An unlocked mutex.
+    const INIT: Self;
+
+    /// Acquires the lock, blocking until it is available.
+    fn lock(&self);
+
+    /// Attempts to acquire the lock without blocking.
+    fn try_lock(&self) -> bool;
+
+    /// Releases the lock.
+    ///
+    /// # Safety
+    /// The lock must be held by the current thread.
+    unsafe fn unlock(&self);
+}
+
+/// Lock used when none is chosen explicitly: [`StdMutex`] with the `std`
+/// feature, [`SpinLock`] without it.
+#[cfg(feature = "std")]
+pub type DefaultRawMutex = StdMutex;
+/// Lock used when none is chosen explicitly: `StdMutex` with the `std`
+/// feature, [`SpinLock`] without it.
+#[cfg(not(feature = "std"))]
+pub type DefaultRawMutex = SpinLock;
+
+/// Lock backed by `std::sync::Mutex`, i.e. the platform's mutex.
+///
+/// The raw interface has no guards, so the guard of the inner mutex is kept
+/// inside the lock while it is held.
+#[cfg(feature = "std")]
+pub struct StdMutex {
+    inner: std::sync::Mutex<()>,
+    guard: UnsafeCell<Option<std::sync::MutexGuard<'static, ()>>>,
+}
+
+// `guard` is only accessed by the thread holding `inner`, which also releases
+// it: `unlock` must be called by the locking thread and the crate's guards are
+// not `Send`.
+#[cfg(feature = "std")]
+unsafe impl Send for StdMutex {}
+#[cfg(feature = "std")]
+unsafe impl Sync for StdMutex {}
+
+#[cfg(feature = "std")]
+unsafe impl RawMutex for StdMutex {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = Self {
+        inner: std::sync::Mutex::new(()),
+        guard: UnsafeCell::new(None),
+    };
+
+    fn lock(&self) {
+        let guard = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        self.store(guard);
+    }
+
+    fn try_lock(&self) -> bool {
+        let guard = match self.inner.try_lock() {
+            Ok(guard) => guard,
+            Err(std::sync::TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(std::sync::TryLockError::WouldBlock) => return false,
+        };
+        self.store(guard);
+        true
+    }
+
+    unsafe fn unlock(&self) {
+        drop((*self.guard.get()).take());
+    }
+}
+
+#[cfg(feature = "std")]
+impl StdMutex {
+    fn store(&self, guard: std::sync::MutexGuard<'_, ()>) {
+        // Safety: the guard borrows `inner`, which cannot move or be dropped
+        // while the lock is held, and it is dropped in `unlock` at the latest.
+        unsafe {
+            *self.guard.get() = Some(mem::transmute::<
+                std::sync::MutexGuard<'_, ()>,
+                std::sync::MutexGuard<'static, ()>,
+            >(guard));
+        }
+    }
+}
+
+/// Lock that spins with exponential backoff instead of blocking.
+///
+/// Avoids the latency of waking a sleeping thread when the lock is only held
+/// briefly, but burns CPU time while waiting. Blocking queue operations still
+/// sleep while waiting for elements or capacity.
+pub struct SpinLock {
+    locked: AtomicBool,
+}
+
+unsafe impl RawMutex for SpinLock {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = Self {
+        locked: AtomicBool::new(false),
+    };
+
+    fn lock(&self) {
+        let mut backoff = Backoff::new();
+        while !self.try_lock() {
+            // Only retry the swap once the lock looks free, so waiting threads
+            // do not keep invalidating the cache line.
+            while self.locked.load(Ordering::Relaxed) {
+                backoff.snooze();
+            }
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        !self.locked.swap(true, Ordering::Acquire)
+    }
+
+    unsafe fn unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+    }
+}
+
+// Exponential backoff for spinning waiters, which eventually yields the CPU.
+struct Backoff {
+    step: u32,
+}
+
+impl Backoff {
+    fn new() -> Self {
+        Self { step: 0 }
+    }
+
+    fn snooze(&mut self) {
+        if self.step < 6 {
+            for _ in 0..1 << self.step {
+                hint::spin_loop();
+            }
+            self.step += 1;
+        } else {
+            yield_now();
+        }
+    }
+}
+
+// Tests link `std` even without the feature. Yielding there keeps spinning
+// waiters from starving the threads they wait for on machines with few cores.
+fn yield_now() {
+    #[cfg(any(feature = "std", test))]
+    std::thread::yield_now();
+    #[cfg(not(any(feature = "std", test)))]
+    hint::spin_loop();
+}
+
+// Without `std` there is no way to tell whether a guard is dropped during
+// unwinding, so mutexes are never poisoned.
+fn panicking() -> bool {
+    #[cfg(feature = "std")]
+    return thread::panicking();
+    #[cfg(not(feature = "std"))]
+    return false;
+}
+
+#[cfg(feature = "parking_lot")]
+unsafe impl RawMutex for parking_lot::RawMutex {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = <Self as parking_lot::lock_api::RawMutex>::INIT;
+
+    fn lock(&self) {
+        parking_lot::lock_api::RawMutex::lock(self);
+    }
+
+    fn try_lock(&self) -> bool {
+        parking_lot::lock_api::RawMutex::try_lock(self)
+    }
+
+    unsafe fn unlock(&self) {
+        parking_lot::lock_api::RawMutex::unlock(self);
+    }
+}
+
+// Mutex on top of a `RawMutex` that is poisoned like `std::sync::Mutex` if a
+// guard is dropped while panicking. Locking ignores the poison flag, the queue
+// only reports it.
+pub(crate) struct Mutex<R, T> {
+    raw: R,
+    poisoned: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<R: RawMutex, T: Send> Send for Mutex<R, T> {}
+unsafe impl<R: RawMutex, T: Send> Sync for Mutex<R, T> {}
+
+impl<R: RawMutex, T> Mutex<R, T> {
+    pub(crate) const fn new(data: T) -> Self {
+        Self {
+            raw: R::INIT,
+            poisoned: AtomicBool::new(false),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, R, T> {
+        self.raw.lock();
+        MutexGuard {
+            mutex: self,
+            panicking: panicking(),
+            _not_send: PhantomData,
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    pub(crate) fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn clear_poison(&self) {
+        self.poisoned.store(false, Ordering::Relaxed);
+    }
+}
+
+pub(crate) struct MutexGuard<'a, R: RawMutex, T> {
+    mutex: &'a Mutex<R, T>,
+    // Only a panic that started while the guard was held poisons the mutex.
+    panicking: bool,
+    // The lock is released by the thread that acquired it, as some `RawMutex`
+    // implementations require.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<R: RawMutex, T> Deref for MutexGuard<'_, R, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<R: RawMutex, T> DerefMut for MutexGuard<'_, R, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<R: RawMutex, T> Drop for MutexGuard<'_, R, T> {
+    fn drop(&mut self) {
+        if !self.panicking && panicking() {
+            self.mutex.poisoned.store(true, Ordering::Relaxed);
+        }
+        unsafe { self.mutex.raw.unlock() };
+    }
+}
+
+// Condition variable usable with any `RawMutex`.
+//
+// A waiter registers while still holding the queue's lock and takes `sleepers`
+// before releasing it. A notifying thread changed the queue state under that
+// lock afterwards, so it either sees no waiter that could miss the change or
+// has to wait for `sleepers` until the waiter sleeps on `wakeup`.
+#[cfg(feature = "std")]
+pub(crate) struct Condvar {
+    waiters: AtomicUsize,
+    sleepers: std::sync::Mutex<()>,
+    wakeup: std::sync::Condvar,
+}
+
+#[cfg(feature = "std")]
+impl Condvar {
+    pub(crate) const fn new() -> Self {
+        Self {
+            waiters: AtomicUsize::new(0),
+            sleepers: std::sync::Mutex::new(()),
+            wakeup: std::sync::Condvar::new(),
+        }
+    }
+
+    // Releases `guard` while waiting and reacquires the lock before
+    // returning. Spurious wakeups are possible.
+    pub(crate) fn wait<'a, R: RawMutex, T>(
+        &self,
+        guard: MutexGuard<'a, R, T>,
+    ) -> MutexGuard<'a, R, T> {
+        self.wait_with(guard, |sleepers| {
+            drop(
+                self.wakeup
+                    .wait(sleepers)
+                    .unwrap_or_else(|e| e.into_inner()),
+            );
+        })
+    }
+
+    pub(crate) fn wait_timeout<'a, R: RawMutex, T>(
+        &self,
+        guard: MutexGuard<'a, R, T>,
+        timeout: Duration,
+    ) -> MutexGuard<'a, R, T> {
+        self.wait_with(guard, |sleepers| {
+            drop(
+                self.wakeup
+                    .wait_timeout(sleepers, timeout)
+                    .unwrap_or_else(|e| e.into_inner()),
+            );
+        })
+    }
+
+    fn wait_with<'a, R: RawMutex, T>(
+        &self,
+        guard: MutexGuard<'a, R, T>,
+        wait: impl FnOnce(std::sync::MutexGuard<'_, ()>),
+    ) -> MutexGuard<'a, R, T> {
+        let mutex = guard.mutex;
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let sleepers = self.sleepers.lock().unwrap_or_else(|e| e.into_inner());
+        drop(guard);
+        wait(sleepers);
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+        mutex.lock()
+    }
+
+    pub(crate) fn notify_one(&self) {
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            let _sleepers = self.sleepers.lock().unwrap_or_else(|e| e.into_inner());
+            self.wakeup.notify_one();
+        }
+    }
+
+    pub(crate) fn notify_all(&self) {
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            let _sleepers = self.sleepers.lock().unwrap_or_else(|e| e.into_inner());
+            self.wakeup.notify_all();
+        }
+    }
+}
+
+// Without `std` waiters spin with backoff until the notification counter
+// changes. The counter is read while the queue's lock is still held, so a
+// notification for a later state change cannot be missed.
+#[cfg(not(feature = "std"))]
+pub(crate) struct Condvar {
+    notifications: AtomicUsize,
+}
+
+#[cfg(not(feature = "std"))]
+impl Condvar {
+    pub(crate) const fn new() -> Self {
+        Self {
+            notifications: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn wait<'a, R: RawMutex, T>(
+        &self,
+        guard: MutexGuard<'a, R, T>,
+    ) -> MutexGuard<'a, R, T> {
+        let mutex = guard.mutex;
+        let notifications = self.notifications.load(Ordering::Acquire);
+        drop(guard);
+        let mut backoff = Backoff::new();
+        while self.notifications.load(Ordering::Acquire) == notifications {
+            backoff.snooze();
+        }
+        mutex.lock()
+    }
+
+    pub(crate) fn notify_one(&self) {
+        self.notifications.fetch_add(1, Ordering::Release);
+    }
+
+    pub(crate) fn notify_all(&self) {
+        self.notifications.fetch_add(1, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mutex, RawMutex, SpinLock};
+    use std::thread;
+    use std::time::Duration;
+
+    fn mutual_exclusion<R: RawMutex>() {
+        let counter = Mutex::<R, usize>::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *counter.lock() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(*counter.lock(), 4000);
+    }
+
+    // Yields inside the critical section, so that waiters pile up and have to
+    // go to sleep instead of acquiring the lock on the fast path.
+    fn contention<R: RawMutex>() {
+        let counter = Mutex::<R, usize>::new(0);
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for i in 0..2000 {
+                        let mut counter = counter.lock();
+                        let value = *counter;
+                        if i % 16 == 0 {
+                            thread::yield_now();
+                        }
+                        *counter = value + 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(*counter.lock(), 16000);
+    }
+
+    // Waiters block while the lock is held and must all be woken up after it
+    // is released. A lost wakeup hangs the test.
+    fn handoff<R: RawMutex>() {
+        let counter = Mutex::<R, usize>::new(0);
+        for round in 1..=20 {
+            let guard = counter.lock();
+            thread::scope(|s| {
+                for _ in 0..4 {
+                    s.spawn(|| *counter.lock() += 1);
+                }
+                thread::sleep(Duration::from_millis(1));
+                assert_eq!(*guard, (round - 1) * 4);
+                drop(guard);
+            });
+            assert_eq!(*counter.lock(), round * 4);
+        }
+    }
+
+    fn stress<R: RawMutex>() {
+        mutual_exclusion::<R>();
+        contention::<R>();
+        handoff::<R>();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn std_mutex() {
+        stress::<super::StdMutex>();
+    }
+
+    #[test]
+    fn spin_lock() {
+        stress::<SpinLock>();
+    }
+
+    #[cfg(feature = "parking_lot")]
+    #[test]
+    fn parking_lot() {
+        stress::<parking_lot::RawMutex>();
+    }
+}
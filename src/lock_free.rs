@@ -0,0 +1,217 @@
+use core::mem::MaybeUninit;
+use core::sync::atomic::Ordering;
+use crossbeam_epoch::{self as epoch, Atomic, Owned, Shared};
+
+/// Lock-free variant of the Michael-Scott queue.
+///
+/// Head and tail are updated with CAS instead of being guarded by a mutex each.
+/// Unlinked nodes are reclaimed through `crossbeam-epoch`, so a node is only
+/// freed once no thread can still be reading it.
+///
+/// Unlike [`TsQueue`](crate::TsQueue), the queue can neither be closed nor
+/// bounded. [`enqueue`](Self::enqueue) therefore cannot fail and
+/// [`dequeue`](Self::dequeue) returns `None` when the queue is empty, instead
+/// of the `Result`s returned by `TsQueue`. Both implement
+/// [`Queue`](crate::Queue) for code that switches between them.
+pub struct LockFreeQueue<T> {
+    head: Atomic<Node<T>>,
+    tail: Atomic<Node<T>>,
+}
+
+// Unlike `TsQueue`, the data of an element lives in the node *after* the dummy
+// head. Once a dequeue swings `head` to that node, the node becomes the new
+// dummy and its data has been moved out.
+struct Node<T> {
+    data: MaybeUninit<T>,
+    next: Atomic<Node<T>>,
+}
+
+unsafe impl<T: Send> Send for LockFreeQueue<T> {}
+unsafe impl<T: Send> Sync for LockFreeQueue<T> {}
+
+impl<T> Default for LockFreeQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> LockFreeQueue<T> {
+    pub fn new() -> Self {
+        let queue = Self {
+            head: Atomic::null(),
+            tail: Atomic::null(),
+        };
+        let dummy = Owned::new(Node {
+            data: MaybeUninit::uninit(),
+            next: Atomic::null(),
+        });
+        // Nobody else can access the queue yet.
+        let guard = unsafe { epoch::unprotected() };
+        let dummy = dummy.into_shared(guard);
+        queue.head.store(dummy, Ordering::Relaxed);
+        queue.tail.store(dummy, Ordering::Relaxed);
+        queue
+    }
+
+    pub fn enqueue(&self, data: T) {
+        let guard = &epoch::pin();
+        let node = Owned::new(Node {
+            data: MaybeUninit::new(data),
+            next: Atomic::null(),
+        })
+        .into_shared(guard);
+        loop {
+            let tail = self.tail.load(Ordering::Acquire, guard);
+            // `tail` is never null and only freed after it was unlinked from
+            // `head`, which requires `tail` to be advanced first.
+            let tail_ref = unsafe { tail.deref() };
+            let next = tail_ref.next.load(Ordering::Acquire, guard);
+            if !next.is_null() {
+                // `tail` is lagging behind, help the other enqueue along.
+                let _ = self.tail.compare_exchange(
+                    tail,
+                    next,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                    guard,
+                );
+                continue;
+            }
+            if tail_ref
+                .next
+                .compare_exchange(
+                    Shared::null(),
+                    node,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                    guard,
+                )
+                .is_ok()
+            {
+                let _ = self.tail.compare_exchange(
+                    tail,
+                    node,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                    guard,
+                );
+                return;
+            }
+        }
+    }
+
+    pub fn dequeue(&self) -> Option<T> {
+        let guard = &epoch::pin();
+        loop {
+            let head = self.head.load(Ordering::Acquire, guard);
+            let next = unsafe { head.deref() }.next.load(Ordering::Acquire, guard);
+            let next_ref = unsafe { next.as_ref() }?;
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed, guard)
+                .is_ok()
+            {
+                // Make sure `tail` does not keep pointing at the node we are
+                // about to retire.
+                let tail = self.tail.load(Ordering::Relaxed, guard);
+                if tail == head {
+                    let _ = self.tail.compare_exchange(
+                        tail,
+                        next,
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                        guard,
+                    );
+                }
+                unsafe {
+                    guard.defer_destroy(head);
+                    // Winning the CAS on `head` grants exclusive ownership of
+                    // the data in `next`.
+                    return Some(next_ref.data.as_ptr().read());
+                }
+            }
+        }
+    }
+}
+
+impl<T> Drop for LockFreeQueue<T> {
+    fn drop(&mut self) {
+        while self.dequeue().is_some() {}
+        unsafe {
+            let guard = epoch::unprotected();
+            let dummy = self.head.load(Ordering::Relaxed, guard);
+            drop(dummy.into_owned());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::LockFreeQueue;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn single_threaded() {
+        let queue = LockFreeQueue::new();
+        for i in 0..5 {
+            queue.enqueue(i);
+        }
+        let data: Vec<_> = std::iter::from_fn(|| queue.dequeue()).collect();
+        assert_eq!(data, (0..5).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn multi_producer_multi_consumer() {
+        const PER_PRODUCER: usize = 1000;
+        let queue = LockFreeQueue::new();
+        let received = AtomicUsize::new(0);
+
+        let mut data: Vec<_> = thread::scope(|s| {
+            for p in 0..4 {
+                let queue = &queue;
+                s.spawn(move || {
+                    for i in 0..PER_PRODUCER {
+                        queue.enqueue(p * PER_PRODUCER + i);
+                    }
+                });
+            }
+            let consumers: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut data = vec![];
+                        while received.load(Ordering::Relaxed) < 4 * PER_PRODUCER {
+                            match queue.dequeue() {
+                                Some(i) => {
+                                    received.fetch_add(1, Ordering::Relaxed);
+                                    data.push(i);
+                                }
+                                None => thread::yield_now(),
+                            }
+                        }
+                        data
+                    })
+                })
+                .collect();
+            consumers
+                .into_iter()
+                .flat_map(|c| c.join().unwrap())
+                .collect()
+        });
+
+        data.sort_unstable();
+        assert_eq!(data, (0..4 * PER_PRODUCER).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn drops_remaining_elements() {
+        let counter = std::sync::Arc::new(());
+        let queue = LockFreeQueue::new();
+        for _ in 0..10 {
+            queue.enqueue(counter.clone());
+        }
+        drop(queue.dequeue());
+        drop(queue);
+        assert_eq!(std::sync::Arc::strong_count(&counter), 1);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::lock::{DefaultRawMutex, Mutex, MutexGuard};
+use crate::Node;
+use alloc::boxed::Box;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// Free list of nodes that have been dequeued and can be reused by later
+// enqueues instead of going through the allocator. Holds at most `max_nodes`
+// nodes, surplus nodes are freed.
+//
+// The free list has its own lock, so with a pool producers and consumers
+// contend on it briefly when allocating and freeing nodes.
+pub(crate) struct NodePool<T> {
+    free: Mutex<DefaultRawMutex, FreeList<T>>,
+    max_nodes: usize,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+    recycled: AtomicUsize,
+    discarded: AtomicUsize,
+    returned_unused: AtomicUsize,
+}
+
+// Intrusive singly linked list threaded through `Node::next`.
+struct FreeList<T> {
+    head: Option<NonNull<Node<T>>>,
+    len: usize,
+}
+
+/// Counters of the node pool of a [`TsQueue`](crate::TsQueue).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Allocations served from the pool.
+    pub hits: usize,
+    /// Allocations that fell back to the allocator because the pool was empty.
+    pub misses: usize,
+    /// Freed nodes returned to the pool.
+    pub recycled: usize,
+    /// Freed nodes handed back to the allocator because the pool was full.
+    pub discarded: usize,
+    /// Allocated nodes given back unused because the enqueue was rejected,
+    /// e.g. on a full or closed queue. Not counted as recycled or discarded.
+    pub returned_unused: usize,
+}
+
+impl PoolStats {
+    /// Fraction of allocations served from the pool, `0.0` if nothing has been
+    /// allocated yet.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+impl<T> NodePool<T> {
+    pub(crate) fn new(max_nodes: usize) -> Self {
+        Self {
+            free: Mutex::new(FreeList { head: None, len: 0 }),
+            max_nodes,
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+            recycled: AtomicUsize::new(0),
+            discarded: AtomicUsize::new(0),
+            returned_unused: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn alloc(&self) -> NonNull<Node<T>> {
+        let mut free = self.lock();
+        match free.head {
+            Some(mut node) => {
+                let node_mut = unsafe { node.as_mut() };
+                free.head = node_mut.take_next();
+                free.len -= 1;
+                drop(free);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                node
+            }
+            None => {
+                drop(free);
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                Node::new()
+            }
+        }
+    }
+
+    // Safety: `node` must be unlinked from the queue and must not be
+    // accessible by anybody else. Its data has to be taken already.
+    pub(crate) unsafe fn free(&self, node: NonNull<Node<T>>) {
+        if self.put(node) {
+            self.recycled.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.discarded.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // Takes back a node from `alloc` that was never linked into the queue.
+    //
+    // Safety: as for `free`.
+    pub(crate) unsafe fn free_unused(&self, node: NonNull<Node<T>>) {
+        self.put(node);
+        self.returned_unused.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Adds `node` to the free list, or frees it if the pool is full. Returns
+    // whether it was kept.
+    unsafe fn put(&self, mut node: NonNull<Node<T>>) -> bool {
+        let mut free = self.lock();
+        if free.len < self.max_nodes {
+            node.as_mut().set_next(free.head);
+            free.head = Some(node);
+            free.len += 1;
+            true
+        } else {
+            drop(free);
+            drop(Box::from_raw(node.as_ptr()));
+            false
+        }
+    }
+
+    pub(crate) fn stats(&self) -> PoolStats {
+        PoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            recycled: self.recycled.load(Ordering::Relaxed),
+            discarded: self.discarded.load(Ordering::Relaxed),
+            returned_unused: self.returned_unused.load(Ordering::Relaxed),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DefaultRawMutex, FreeList<T>> {
+        self.free.lock()
+    }
+}
+
+impl<T> Drop for NodePool<T> {
+    fn drop(&mut self) {
+        let free = self.free.get_mut();
+        let mut node = free.head.take();
+        while let Some(next) = node {
+            node = unsafe { Box::from_raw(next.as_ptr()) }.take_next();
+        }
+    }
+}
@@ -0,0 +1,681 @@
+use crate::clock::Clock;
+#[cfg(feature = "std")]
+use crate::clock::MonotonicClock;
+#[cfg(feature = "std")]
+use crate::error::DequeueTimeoutError;
+use crate::error::{Closed, DequeueError, TryDequeueError, TryEnqueueError};
+use crate::flow::FlowControl;
+use crate::lock::{DefaultRawMutex, Mutex, MutexGuard};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cmp::Ordering as CmpOrdering;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+/// Thread-safe priority queue that dequeues the greatest element first.
+///
+/// Elements are either ordered by `T` itself, see [`new`](TsPriorityQueue::new),
+/// or by a key extracted once on enqueue, see [`by_key`](TsPriorityQueue::by_key).
+/// `K` is the type of that key and [`NoKey`] for queues ordered by `T`. Elements
+/// that compare equal are dequeued in the order they were enqueued.
+///
+/// Offers the same enqueue and dequeue operations as [`TsQueue`](crate::TsQueue),
+/// including blocking, bounded and closed queues. A single lock guards the
+/// heap, since every operation may reorder it.
+///
+/// # Aging
+/// Under a steady load of high priority elements, low priority ones may never
+/// be dequeued. An aging policy, set with [`with_aging`](Self::with_aging) or
+/// [`with_aging_clock`](Self::with_aging_clock), prevents this by raising the
+/// priority of elements the longer they wait. Only keys are aged, so aging is
+/// only available for queues created with [`by_key`](Self::by_key) or
+/// [`by_key_bounded`](Self::by_key_bounded). The current effective priorities
+/// can be inspected with [`effective_priorities`](Self::effective_priorities).
+pub struct TsPriorityQueue<T, K = NoKey> {
+    heap: Mutex<DefaultRawMutex, Heap<T, K>>,
+    key: Box<dyn Fn(&T) -> K + Send + Sync>,
+    // Paired with the heap mutex.
+    flow: FlowControl,
+    len: AtomicUsize,
+}
+
+/// Key type of queues ordered by their elements, see
+/// [`TsPriorityQueue::new`]. It has no order of its own, which keeps the
+/// key-based [`with_aging`](TsPriorityQueue::with_aging) unavailable for them.
+///
+/// ```compile_fail
+/// use ts_queue::{ManualClock, TsPriorityQueue};
+///
+/// let queue = TsPriorityQueue::<u32>::new().with_aging_clock(|key, _| *key, ManualClock::new());
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NoKey;
+
+struct Heap<T, K> {
+    entries: Vec<Entry<T, K>>,
+    next_seq: u64,
+    cmp: fn(&Entry<T, K>, &Entry<T, K>) -> CmpOrdering,
+    aging: Option<Aging<K>>,
+}
+
+struct Entry<T, K> {
+    data: T,
+    key: K,
+    seq: u64,
+    // Time of the enqueue according to the aging clock, zero without aging.
+    enqueued_at: Duration,
+}
+
+// Maps a key and the time its element has been waiting to its effective
+// priority.
+type AgingPolicy<K> = Box<dyn Fn(&K, Duration) -> K + Send + Sync>;
+
+struct Aging<K> {
+    policy: AgingPolicy<K>,
+    clock: Box<dyn Clock>,
+    cmp: fn(&K, &K) -> CmpOrdering,
+}
+
+impl<T: Ord> Default for TsPriorityQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> TsPriorityQueue<T> {
+    pub fn new() -> Self {
+        Self::with_config(None, |_| NoKey, |a, b| a.data.cmp(&b.data))
+    }
+
+    /// Creates a queue holding at most `capacity` elements, see
+    /// [`TsQueue::bounded`](crate::TsQueue::bounded).
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero.
+    pub fn bounded(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity of a bounded queue must be non-zero");
+        Self::with_config(Some(capacity), |_| NoKey, |a, b| a.data.cmp(&b.data))
+    }
+}
+
+impl<T, K: Ord> TsPriorityQueue<T, K> {
+    /// Creates a queue ordered by the key `f` returns for each element. The key
+    /// is computed once when the element is enqueued, outside of the lock.
+    pub fn by_key(f: impl Fn(&T) -> K + Send + Sync + 'static) -> Self {
+        Self::with_config(None, f, |a, b| a.key.cmp(&b.key))
+    }
+
+    /// Bounded variant of [`by_key`](Self::by_key).
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero.
+    pub fn by_key_bounded(capacity: usize, f: impl Fn(&T) -> K + Send + Sync + 'static) -> Self {
+        assert!(capacity > 0, "capacity of a bounded queue must be non-zero");
+        Self::with_config(Some(capacity), f, |a, b| a.key.cmp(&b.key))
+    }
+
+    /// Enables aging with a [`MonotonicClock`], see
+    /// [`with_aging_clock`](Self::with_aging_clock).
+    #[cfg(feature = "std")]
+    pub fn with_aging(self, policy: impl Fn(&K, Duration) -> K + Send + Sync + 'static) -> Self {
+        self.with_aging_clock(policy, MonotonicClock::new())
+    }
+
+    /// Enables aging: elements are ordered by the effective priority `policy`
+    /// returns for their key and the time they have been waiting according to
+    /// `clock`. Elements already in the queue start aging now.
+    ///
+    /// Since effective priorities change over time, dequeueing scans all
+    /// elements instead of maintaining a heap and takes O(n).
+    pub fn with_aging_clock(
+        mut self,
+        policy: impl Fn(&K, Duration) -> K + Send + Sync + 'static,
+        clock: impl Clock + 'static,
+    ) -> Self {
+        let heap = self.heap.get_mut();
+        let now = clock.now();
+        for entry in &mut heap.entries {
+            entry.enqueued_at = now;
+        }
+        heap.aging = Some(Aging {
+            policy: Box::new(policy),
+            clock: Box::new(clock),
+            cmp: K::cmp,
+        });
+        self
+    }
+}
+
+impl<T, K> TsPriorityQueue<T, K> {
+    fn with_config(
+        capacity: Option<usize>,
+        key: impl Fn(&T) -> K + Send + Sync + 'static,
+        cmp: fn(&Entry<T, K>, &Entry<T, K>) -> CmpOrdering,
+    ) -> Self {
+        Self {
+            heap: Mutex::new(Heap {
+                entries: Vec::new(),
+                next_seq: 0,
+                cmp,
+                aging: None,
+            }),
+            key: Box::new(key),
+            flow: FlowControl::new(capacity),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the capacity of a bounded queue or `None` if it is unbounded.
+    pub fn capacity(&self) -> Option<usize> {
+        self.flow.capacity()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.flow.is_full(self.len())
+    }
+
+    /// See [`TsQueue::enqueue`](crate::TsQueue::enqueue).
+    pub fn enqueue(&self, data: T) -> Result<(), Closed<T>> {
+        let key = (self.key)(&data);
+        match self.flow.wait_for_room(self.heap.lock(), || self.len()) {
+            Some(heap) => {
+                self.push(heap, data, key);
+                Ok(())
+            }
+            None => Err(Closed(data)),
+        }
+    }
+
+    /// See [`TsQueue::try_enqueue`](crate::TsQueue::try_enqueue).
+    pub fn try_enqueue(&self, data: T) -> Result<(), TryEnqueueError<T>> {
+        if self.is_poisoned() {
+            return Err(TryEnqueueError::Poisoned(data));
+        }
+        let key = (self.key)(&data);
+        let heap = self.heap.lock();
+        let data = self.flow.check_room(data, self.len())?;
+        self.push(heap, data, key);
+        Ok(())
+    }
+
+    fn push(&self, heap: MutexGuard<'_, DefaultRawMutex, Heap<T, K>>, data: T, key: K) {
+        LenUpdate::new(self, heap).push(data, key);
+    }
+
+    /// Dequeues the greatest element without blocking, see
+    /// [`TsQueue::dequeue`](crate::TsQueue::dequeue).
+    pub fn dequeue(&self) -> Result<T, DequeueError> {
+        self.flow.dequeue(|| self.pop().ok())
+    }
+
+    /// See [`TsQueue::try_dequeue`](crate::TsQueue::try_dequeue).
+    pub fn try_dequeue(&self) -> Result<T, TryDequeueError> {
+        if self.is_poisoned() {
+            return Err(TryDequeueError::Poisoned);
+        }
+        self.dequeue().map_err(Into::into)
+    }
+
+    /// Dequeues the greatest element, blocking until one is available, see
+    /// [`TsQueue::dequeue_blocking`](crate::TsQueue::dequeue_blocking).
+    pub fn dequeue_blocking(&self) -> Option<T> {
+        self.flow.dequeue_blocking(|| self.pop())
+    }
+
+    /// See [`TsQueue::dequeue_timeout`](crate::TsQueue::dequeue_timeout).
+    #[cfg(feature = "std")]
+    pub fn dequeue_timeout(&self, timeout: Duration) -> Result<T, DequeueTimeoutError> {
+        self.flow.dequeue_timeout(timeout, || self.pop())
+    }
+
+    /// See [`TsQueue::dequeue_deadline`](crate::TsQueue::dequeue_deadline).
+    #[cfg(feature = "std")]
+    pub fn dequeue_deadline(&self, deadline: Instant) -> Result<T, DequeueTimeoutError> {
+        self.flow.dequeue_deadline(deadline, || self.pop())
+    }
+
+    // Pops the greatest element, or hands the guard back if the heap is empty
+    // so that callers can wait on `not_empty` without missing an enqueue.
+    fn pop(&self) -> Result<T, MutexGuard<'_, DefaultRawMutex, Heap<T, K>>> {
+        let heap = self.heap.lock();
+        if heap.entries.is_empty() {
+            return Err(heap);
+        }
+        Ok(LenUpdate::new(self, heap).pop().unwrap())
+    }
+
+    /// Runs `f` on the greatest element without removing it and returns its
+    /// result, or `None` if the queue is empty.
+    ///
+    /// The lock is held while `f` runs, so all other operations are blocked
+    /// until it returns.
+    pub fn peek_with<U>(&self, f: impl FnOnce(&T) -> U) -> Option<U> {
+        let heap = self.heap.lock();
+        heap.front().map(|i| f(&heap.entries[i].data))
+    }
+
+    /// Returns a clone of the greatest element, see
+    /// [`peek_with`](Self::peek_with).
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.peek_with(T::clone)
+    }
+
+    /// Returns clones of all elements together with their current effective
+    /// priority, in the order they would be dequeued. Without aging, the
+    /// effective priority is the key.
+    pub fn effective_priorities(&self) -> Vec<(T, K)>
+    where
+        T: Clone,
+        K: Clone,
+    {
+        let heap = self.heap.lock();
+        let mut entries: Vec<_> = match &heap.aging {
+            Some(aging) => {
+                let now = aging.clock.now();
+                heap.entries
+                    .iter()
+                    .map(|entry| (entry, aging.effective(entry, now)))
+                    .collect()
+            }
+            None => heap
+                .entries
+                .iter()
+                .map(|entry| (entry, entry.key.clone()))
+                .collect(),
+        };
+        entries.sort_by(|(a, pa), (b, pb)| heap.rank(a, pa, b, pb));
+        entries
+            .into_iter()
+            .map(|(entry, priority)| (entry.data.clone(), priority))
+            .collect()
+    }
+
+    /// See [`TsQueue::close`](crate::TsQueue::close).
+    pub fn close(&self) {
+        self.flow.close(self.heap.lock());
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.flow.is_closed()
+    }
+
+    /// Returns `true` if a thread panicked while holding the queue's lock, e.g.
+    /// in the `Ord` implementation of `T` or `K`.
+    pub fn is_poisoned(&self) -> bool {
+        self.heap.is_poisoned()
+    }
+
+    /// See [`TsQueue::clear_poison`](crate::TsQueue::clear_poison).
+    pub fn clear_poison(&self) {
+        self.heap.clear_poison();
+    }
+}
+
+// Holds the heap lock while an element is pushed or popped. On drop, `len` is
+// set to the number of entries and the threads waiting for the change are woken
+// up. This also happens if a panicking comparison unwinds through a push or
+// pop, which may already have added or removed the element.
+struct LenUpdate<'a, T, K> {
+    queue: &'a TsPriorityQueue<T, K>,
+    heap: Option<MutexGuard<'a, DefaultRawMutex, Heap<T, K>>>,
+    old_len: usize,
+}
+
+impl<'a, T, K> LenUpdate<'a, T, K> {
+    fn new(
+        queue: &'a TsPriorityQueue<T, K>,
+        heap: MutexGuard<'a, DefaultRawMutex, Heap<T, K>>,
+    ) -> Self {
+        Self {
+            queue,
+            old_len: heap.entries.len(),
+            heap: Some(heap),
+        }
+    }
+}
+
+impl<T, K> Deref for LenUpdate<'_, T, K> {
+    type Target = Heap<T, K>;
+
+    fn deref(&self) -> &Heap<T, K> {
+        self.heap.as_ref().unwrap()
+    }
+}
+
+impl<T, K> DerefMut for LenUpdate<'_, T, K> {
+    fn deref_mut(&mut self) -> &mut Heap<T, K> {
+        self.heap.as_mut().unwrap()
+    }
+}
+
+impl<T, K> Drop for LenUpdate<'_, T, K> {
+    fn drop(&mut self) {
+        let heap = match self.heap.take() {
+            Some(heap) => heap,
+            None => return,
+        };
+        let len = heap.entries.len();
+        self.queue.len.store(len, Ordering::Release);
+        drop(heap);
+        let flow = &self.queue.flow;
+        if len > self.old_len {
+            flow.not_empty.notify_one();
+        } else if len < self.old_len && flow.is_full(self.old_len) {
+            flow.not_full.notify_all();
+        }
+    }
+}
+
+// Without aging, the entries form a binary max-heap. Entries are only ever
+// swapped, so a panicking comparison leaves a valid, if possibly misordered,
+// heap behind. With aging, the order changes as time passes, so the entries are
+// kept unordered and scanned for the front one instead.
+impl<T, K> Heap<T, K> {
+    fn push(&mut self, data: T, key: K) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let enqueued_at = match &self.aging {
+            Some(aging) => aging.clock.now(),
+            None => Duration::ZERO,
+        };
+        self.entries.push(Entry {
+            data,
+            key,
+            seq,
+            enqueued_at,
+        });
+        if self.aging.is_some() {
+            return;
+        }
+        let mut i = self.entries.len() - 1;
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if !self.before(i, parent) {
+                break;
+            }
+            self.entries.swap(i, parent);
+            i = parent;
+        }
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        if self.aging.is_some() {
+            let front = self.front()?;
+            return Some(self.entries.swap_remove(front).data);
+        }
+        let last = self.entries.len().checked_sub(1)?;
+        self.entries.swap(0, last);
+        let entry = self.entries.pop()?;
+        let mut i = 0;
+        loop {
+            let left = 2 * i + 1;
+            if left >= self.entries.len() {
+                break;
+            }
+            let right = left + 1;
+            let child = if right < self.entries.len() && self.before(right, left) {
+                right
+            } else {
+                left
+            };
+            if !self.before(child, i) {
+                break;
+            }
+            self.entries.swap(child, i);
+            i = child;
+        }
+        Some(entry.data)
+    }
+
+    // Returns the index of the entry that is dequeued next.
+    fn front(&self) -> Option<usize> {
+        let aging = match &self.aging {
+            Some(aging) => aging,
+            None if self.entries.is_empty() => return None,
+            None => return Some(0),
+        };
+        let now = aging.clock.now();
+        let mut front: Option<(usize, K)> = None;
+        for (i, entry) in self.entries.iter().enumerate() {
+            let priority = aging.effective(entry, now);
+            if let Some((j, front_priority)) = &front {
+                let front_entry = &self.entries[*j];
+                if self.rank(entry, &priority, front_entry, front_priority) != CmpOrdering::Less {
+                    continue;
+                }
+            }
+            front = Some((i, priority));
+        }
+        front.map(|(i, _)| i)
+    }
+
+    // Whether the entry at `a` is dequeued before the one at `b`, see `rank`.
+    fn before(&self, a: usize, b: usize) -> bool {
+        let (a, b) = (&self.entries[a], &self.entries[b]);
+        self.rank(a, &a.key, b, &b.key) == CmpOrdering::Less
+    }
+
+    // Orders `a` before `b` if it is dequeued first. With aging, the entries
+    // are compared by their effective priorities `pa` and `pb`. Equal entries
+    // are ordered by their sequence number, i.e. FIFO.
+    fn rank(&self, a: &Entry<T, K>, pa: &K, b: &Entry<T, K>, pb: &K) -> CmpOrdering {
+        let order = match &self.aging {
+            Some(aging) => (aging.cmp)(pb, pa),
+            None => (self.cmp)(b, a),
+        };
+        order.then_with(|| a.seq.cmp(&b.seq))
+    }
+}
+
+impl<K> Aging<K> {
+    fn effective<T>(&self, entry: &Entry<T, K>, now: Duration) -> K {
+        (self.policy)(&entry.key, now.saturating_sub(entry.enqueued_at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Closed, DequeueError, ManualClock, TryEnqueueError, TsPriorityQueue};
+    use core::cmp::Ordering;
+    use std::cell::Cell;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn priority_order() {
+        let queue = TsPriorityQueue::new();
+        for i in [3, 1, 4, 1, 5, 9, 2, 6] {
+            queue.enqueue(i).unwrap();
+        }
+        assert_eq!(queue.peek(), Some(9));
+        let data: Vec<_> = std::iter::from_fn(|| queue.dequeue().ok()).collect();
+        assert_eq!(data, [9, 6, 5, 4, 3, 2, 1, 1]);
+        assert_eq!(queue.dequeue(), Err(DequeueError::Empty));
+
+        // Jobs of equal priority keep their enqueue order.
+        let queue = TsPriorityQueue::by_key(|job: &(u8, char)| job.0);
+        for job in [(1, 'a'), (2, 'b'), (1, 'c'), (2, 'd'), (1, 'e'), (2, 'f')] {
+            queue.enqueue(job).unwrap();
+        }
+        let data: Vec<_> = std::iter::from_fn(|| queue.dequeue().ok())
+            .map(|job| job.1)
+            .collect();
+        assert_eq!(data, ['b', 'd', 'f', 'a', 'c', 'e']);
+    }
+
+    #[test]
+    fn bounded_and_closed() {
+        let queue = TsPriorityQueue::bounded(2);
+        queue.enqueue(1).unwrap();
+        queue.enqueue(2).unwrap();
+        assert!(queue.is_full());
+        assert_eq!(queue.try_enqueue(3), Err(TryEnqueueError::Full(3)));
+
+        thread::scope(|s| {
+            // Blocks until the consumer makes room.
+            let producer = s.spawn(|| queue.enqueue(3));
+            assert_eq!(queue.dequeue_blocking(), Some(2));
+            producer.join().unwrap().unwrap();
+        });
+        assert_eq!(queue.len(), 2);
+
+        queue.close();
+        assert_eq!(queue.enqueue(4), Err(Closed(4)));
+        assert_eq!(queue.dequeue_blocking(), Some(3));
+        assert_eq!(queue.dequeue_blocking(), Some(1));
+        assert_eq!(queue.dequeue_blocking(), None);
+        assert_eq!(queue.dequeue(), Err(DequeueError::Closed));
+    }
+
+    #[test]
+    fn multiple_producers_and_consumers() {
+        let queue = TsPriorityQueue::bounded(16);
+        let data_expected: Vec<_> = (0..10000).collect();
+
+        let mut data_recv = thread::scope(|s| {
+            let consumers: Vec<_> = (0..2)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut data = vec![];
+                        while let Some(i) = queue.dequeue_blocking() {
+                            data.push(i);
+                        }
+                        data
+                    })
+                })
+                .collect();
+            let producers: Vec<_> = data_expected
+                .chunks(2500)
+                .map(|chunk| {
+                    let queue = &queue;
+                    s.spawn(move || {
+                        for i in chunk {
+                            queue.enqueue(*i).unwrap();
+                        }
+                    })
+                })
+                .collect();
+            for producer in producers {
+                producer.join().unwrap();
+            }
+            queue.close();
+            consumers
+                .into_iter()
+                .flat_map(|consumer| consumer.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        data_recv.sort_unstable();
+        assert_eq!(data_recv, data_expected);
+    }
+
+    #[test]
+    fn aging() {
+        let clock = ManualClock::new();
+        let queue = TsPriorityQueue::by_key(|job: &(u32, char)| job.0).with_aging_clock(
+            |&priority, age| priority + age.as_secs() as u32,
+            clock.clone(),
+        );
+        queue.enqueue((1, 'a')).unwrap();
+        clock.advance(Duration::from_secs(3));
+        queue.enqueue((5, 'b')).unwrap();
+        queue.enqueue((2, 'c')).unwrap();
+        assert_eq!(
+            queue.effective_priorities(),
+            [((5, 'b'), 5), ((1, 'a'), 4), ((2, 'c'), 2)]
+        );
+
+        // Ties keep their enqueue order.
+        clock.advance(Duration::from_secs(2));
+        queue.enqueue((7, 'd')).unwrap();
+        assert_eq!(
+            queue.effective_priorities(),
+            [((5, 'b'), 7), ((7, 'd'), 7), ((1, 'a'), 6), ((2, 'c'), 4)]
+        );
+        assert_eq!(queue.dequeue(), Ok((5, 'b')));
+        assert_eq!(queue.dequeue(), Ok((7, 'd')));
+
+        // 'a' has waited long enough to overtake a new job of higher priority.
+        queue.enqueue((5, 'e')).unwrap();
+        assert_eq!(queue.peek(), Some((1, 'a')));
+        assert_eq!(queue.dequeue(), Ok((1, 'a')));
+        assert_eq!(queue.dequeue(), Ok((5, 'e')));
+        assert_eq!(queue.dequeue(), Ok((2, 'c')));
+        assert_eq!(queue.dequeue(), Err(DequeueError::Empty));
+    }
+
+    #[test]
+    fn panicking_ord() {
+        // Only comparisons on the current thread panic.
+        thread_local!(static ARMED: Cell<bool> = const { Cell::new(false) });
+
+        #[derive(PartialEq, Eq)]
+        struct Key(u32);
+
+        impl PartialOrd for Key {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for Key {
+            fn cmp(&self, other: &Self) -> Ordering {
+                assert!(!ARMED.with(Cell::get), "comparison failed");
+                self.0.cmp(&other.0)
+            }
+        }
+
+        let queue = TsPriorityQueue::by_key_bounded(3, |x: &u32| Key(*x));
+        for i in 1..=3 {
+            queue.enqueue(i).unwrap();
+        }
+
+        // The element is removed before the heap is restored.
+        ARMED.with(|armed| armed.set(true));
+        let res = panic::catch_unwind(AssertUnwindSafe(|| queue.dequeue()));
+        assert!(res.is_err());
+        assert_eq!(queue.len(), 2);
+        assert!(!queue.is_full());
+
+        // The element is added before the heap is restored.
+        let res = panic::catch_unwind(AssertUnwindSafe(|| queue.enqueue(4)));
+        assert!(res.is_err());
+        assert_eq!(queue.len(), 3);
+        assert!(queue.is_full());
+
+        // A producer waiting for room is woken up by a panicking dequeue.
+        thread::scope(|s| {
+            let producer = s.spawn(|| queue.enqueue(5));
+            while queue.len() == 3 {
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| queue.dequeue()));
+            }
+            producer.join().unwrap().unwrap();
+        });
+        ARMED.with(|armed| armed.set(false));
+
+        let len = queue.len();
+        let mut drained = 0;
+        while queue.dequeue().is_ok() {
+            drained += 1;
+        }
+        assert_eq!(drained, len);
+        assert_eq!(queue.len(), 0);
+        queue.enqueue(6).unwrap();
+        assert_eq!(queue.dequeue(), Ok(6));
+    }
+}
@@ -0,0 +1,100 @@
+#[cfg(feature = "std")]
+use crate::LockFreeQueue;
+use crate::{RawMutex, SegmentedTsQueue, TsQueue};
+
+/// Operations shared by the unbounded FIFO queues of this crate, so that
+/// generic code, e.g. a benchmark, can switch between them.
+///
+/// The inherent methods of each queue offer more, such as blocking or closing
+/// a [`TsQueue`]. They take precedence over the trait methods of the same name
+/// unless the queue is used through the trait.
+pub trait Queue<T>: Sync {
+    /// Creates an empty queue.
+    fn new() -> Self
+    where
+        Self: Sized;
+
+    /// Enqueues `data`, handing it back if the queue does not accept it, e.g.
+    /// because it has been closed.
+    fn enqueue(&self, data: T) -> Result<(), T>;
+
+    /// Dequeues the oldest element without blocking, returns `None` if the
+    /// queue is currently empty.
+    fn dequeue(&self) -> Option<T>;
+}
+
+impl<T: Send, R: RawMutex> Queue<T> for TsQueue<T, R> {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn enqueue(&self, data: T) -> Result<(), T> {
+        TsQueue::enqueue(self, data).map_err(|err| err.0)
+    }
+
+    fn dequeue(&self) -> Option<T> {
+        TsQueue::dequeue(self).ok()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Send> Queue<T> for LockFreeQueue<T> {
+    fn new() -> Self {
+        LockFreeQueue::new()
+    }
+
+    fn enqueue(&self, data: T) -> Result<(), T> {
+        LockFreeQueue::enqueue(self, data);
+        Ok(())
+    }
+
+    fn dequeue(&self) -> Option<T> {
+        LockFreeQueue::dequeue(self)
+    }
+}
+
+impl<T: Send, const N: usize> Queue<T> for SegmentedTsQueue<T, N> {
+    fn new() -> Self {
+        SegmentedTsQueue::new()
+    }
+
+    fn enqueue(&self, data: T) -> Result<(), T> {
+        SegmentedTsQueue::enqueue(self, data);
+        Ok(())
+    }
+
+    fn dequeue(&self) -> Option<T> {
+        SegmentedTsQueue::dequeue(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "std")]
+    use crate::LockFreeQueue;
+    use crate::{Queue, SegmentedTsQueue, TsQueue};
+
+    fn fifo<Q: Queue<i32>>() {
+        let queue = Q::new();
+        for i in 0..10 {
+            queue.enqueue(i).unwrap();
+        }
+        let data: Vec<_> = std::iter::from_fn(|| queue.dequeue()).collect();
+        assert_eq!(data, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn all_queues_are_fifo() {
+        fifo::<TsQueue<i32>>();
+        fifo::<SegmentedTsQueue<i32, 4>>();
+        #[cfg(feature = "std")]
+        fifo::<LockFreeQueue<i32>>();
+    }
+
+    #[test]
+    fn closed_queue_hands_back_data() {
+        let queue = TsQueue::new();
+        queue.close();
+        assert_eq!(Queue::enqueue(&queue, 1), Err(1));
+    }
+}
@@ -0,0 +1,183 @@
+use crate::lock::{DefaultRawMutex, Mutex};
+use crate::padded::CachePadded;
+use alloc::boxed::Box;
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+/// Two-lock queue storing its elements in segments of `N` slots.
+///
+/// Uses the same protocol as [`TsQueue`](crate::TsQueue), producers only take
+/// the tail lock and consumers the head lock, but a node holds `N` elements
+/// instead of one. This divides the number of allocations by `N` and keeps
+/// consecutive elements next to each other in memory, which pays off for small
+/// `T`.
+pub struct SegmentedTsQueue<T, const N: usize = 32> {
+    head: CachePadded<Mutex<DefaultRawMutex, Position<T, N>>>,
+    tail: CachePadded<Mutex<DefaultRawMutex, Position<T, N>>>,
+    len: AtomicUsize,
+}
+
+// Slots `0..idx` of the tail segment are written, slots `idx..N` of the head
+// segment have not been read yet. An index of `N` means the segment is used up
+// and its successor has not been linked yet.
+struct Position<T, const N: usize> {
+    segment: NonNull<Segment<T, N>>,
+    idx: usize,
+}
+
+// `written` and `next` are published with release ordering after the slots
+// and the successor have been initialized, so consumers detect a non-empty
+// queue from the head segment without taking the tail lock.
+struct Segment<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    written: AtomicUsize,
+    next: AtomicPtr<Segment<T, N>>,
+}
+
+impl<T, const N: usize> Segment<T, N> {
+    fn new() -> NonNull<Self> {
+        Box::leak(Box::new(Self {
+            slots: core::array::from_fn(|_| UnsafeCell::new(MaybeUninit::uninit())),
+            written: AtomicUsize::new(0),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+        .into()
+    }
+}
+
+unsafe impl<T: Send, const N: usize> Send for SegmentedTsQueue<T, N> {}
+unsafe impl<T: Send, const N: usize> Sync for SegmentedTsQueue<T, N> {}
+
+impl<T, const N: usize> Default for SegmentedTsQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> SegmentedTsQueue<T, N> {
+    /// # Panics
+    /// Panics if the segment size `N` is zero.
+    pub fn new() -> Self {
+        assert!(N > 0, "segment size must be non-zero");
+        let segment = Segment::new();
+        Self {
+            head: CachePadded::new(Mutex::new(Position { segment, idx: 0 })),
+            tail: CachePadded::new(Mutex::new(Position { segment, idx: 0 })),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn enqueue(&self, data: T) {
+        let mut tail = self.tail.lock();
+        if tail.idx == N {
+            // Only every N-th enqueue has to allocate.
+            let segment = Segment::new();
+            // Consumers may be reading slots of the current segment, so no
+            // reference to the whole segment is created.
+            unsafe {
+                (*tail.segment.as_ptr())
+                    .next
+                    .store(segment.as_ptr(), Ordering::Release)
+            };
+            *tail = Position { segment, idx: 0 };
+        }
+        self.len.fetch_add(1, Ordering::Release);
+        unsafe {
+            let slot = (*tail.segment.as_ptr()).slots[tail.idx].get();
+            (*slot).write(data);
+        }
+        tail.idx += 1;
+        unsafe {
+            (*tail.segment.as_ptr())
+                .written
+                .store(tail.idx, Ordering::Release)
+        };
+    }
+
+    pub fn dequeue(&self) -> Option<T> {
+        let mut head = self.head.lock();
+        let mut used_up = None;
+        if head.idx == N {
+            let next = unsafe { (*head.segment.as_ptr()).next.load(Ordering::Acquire) };
+            let segment = NonNull::new(next)?;
+            // The successor is only linked once all slots are written, and
+            // producers never return to the used up segment.
+            used_up = Some(head.segment);
+            *head = Position { segment, idx: 0 };
+        }
+        let written = unsafe { (*head.segment.as_ptr()).written.load(Ordering::Acquire) };
+        let data = if head.idx < written {
+            let data = unsafe {
+                let slot = (*head.segment.as_ptr()).slots[head.idx].get();
+                (*slot).assume_init_read()
+            };
+            head.idx += 1;
+            self.len.fetch_sub(1, Ordering::AcqRel);
+            Some(data)
+        } else {
+            None
+        };
+        drop(head);
+        if let Some(segment) = used_up {
+            unsafe { drop(Box::from_raw(segment.as_ptr())) };
+        }
+        data
+    }
+}
+
+impl<T, const N: usize> Drop for SegmentedTsQueue<T, N> {
+    fn drop(&mut self) {
+        while self.dequeue().is_some() {}
+        let head = self.head.get_mut();
+        unsafe { drop(Box::from_raw(head.segment.as_ptr())) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::roundtrip;
+    use crate::SegmentedTsQueue;
+    use std::sync::Arc;
+
+    #[test]
+    fn single_threaded() {
+        let queue: SegmentedTsQueue<i32, 4> = SegmentedTsQueue::new();
+        for round in 0..3 {
+            for i in 0..10 {
+                queue.enqueue(round * 10 + i);
+            }
+            assert_eq!(queue.len(), 10);
+            let data: Vec<_> = std::iter::from_fn(|| queue.dequeue()).collect();
+            assert_eq!(data, (round * 10..round * 10 + 10).collect::<Vec<_>>());
+            assert!(queue.is_empty());
+        }
+    }
+
+    #[test]
+    fn multi_threaded() {
+        let queue: SegmentedTsQueue<i32, 7> = SegmentedTsQueue::new();
+        roundtrip(|i| queue.enqueue(i), || queue.dequeue());
+    }
+
+    #[test]
+    fn drops_remaining_elements() {
+        let counter = Arc::new(());
+        let queue: SegmentedTsQueue<_, 3> = SegmentedTsQueue::new();
+        for _ in 0..10 {
+            queue.enqueue(counter.clone());
+        }
+        drop(queue.dequeue());
+        drop(queue);
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::lock::{DefaultRawMutex, Mutex};
+use crate::padded::CachePadded;
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Two-lock queue with a fixed capacity of `N` elements stored inline.
+///
+/// Never allocates, and `new` is a `const fn`, so the queue can be placed in a
+/// `static`. Like [`TsQueue`](crate::TsQueue), producers only take the tail
+/// lock and consumers the head lock.
+pub struct StaticTsQueue<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    // Index of the next slot to read resp. write. Both wrap around at `N`.
+    head: CachePadded<Mutex<DefaultRawMutex, usize>>,
+    tail: CachePadded<Mutex<DefaultRawMutex, usize>>,
+    // Number of written slots. A producer only writes a slot after observing
+    // that the consumer has released it and vice versa.
+    len: AtomicUsize,
+}
+
+unsafe impl<T: Send, const N: usize> Send for StaticTsQueue<T, N> {}
+unsafe impl<T: Send, const N: usize> Sync for StaticTsQueue<T, N> {}
+
+impl<T, const N: usize> Default for StaticTsQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> StaticTsQueue<T, N> {
+    /// # Panics
+    /// Panics if the capacity `N` is zero.
+    pub const fn new() -> Self {
+        assert!(N > 0, "capacity must be non-zero");
+        Self {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: CachePadded::new(Mutex::new(0)),
+            tail: CachePadded::new(Mutex::new(0)),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == N
+    }
+
+    /// Enqueues `data`, or hands it back if the queue is full.
+    pub fn enqueue(&self, data: T) -> Result<(), T> {
+        let mut tail = self.tail.lock();
+        if self.len.load(Ordering::Acquire) == N {
+            return Err(data);
+        }
+        unsafe { (*self.slots[*tail].get()).write(data) };
+        *tail = (*tail + 1) % N;
+        self.len.fetch_add(1, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn dequeue(&self) -> Option<T> {
+        let mut head = self.head.lock();
+        if self.len.load(Ordering::Acquire) == 0 {
+            return None;
+        }
+        let data = unsafe { (*self.slots[*head].get()).assume_init_read() };
+        *head = (*head + 1) % N;
+        self.len.fetch_sub(1, Ordering::Release);
+        Some(data)
+    }
+}
+
+impl<T, const N: usize> Drop for StaticTsQueue<T, N> {
+    fn drop(&mut self) {
+        while self.dequeue().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::roundtrip;
+    use crate::StaticTsQueue;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn single_threaded() {
+        let queue: StaticTsQueue<i32, 4> = StaticTsQueue::new();
+        for round in 0..3 {
+            for i in 0..4 {
+                queue.enqueue(round * 4 + i).unwrap();
+            }
+            assert!(queue.is_full());
+            assert_eq!(queue.enqueue(-1), Err(-1));
+            let data: Vec<_> = std::iter::from_fn(|| queue.dequeue()).collect();
+            assert_eq!(data, (round * 4..round * 4 + 4).collect::<Vec<_>>());
+            assert!(queue.is_empty());
+        }
+    }
+
+    #[test]
+    fn static_item() {
+        static QUEUE: StaticTsQueue<i32, 8> = StaticTsQueue::new();
+        roundtrip(
+            |mut i| {
+                while let Err(rejected) = QUEUE.enqueue(i) {
+                    i = rejected;
+                    thread::yield_now();
+                }
+            },
+            || QUEUE.dequeue(),
+        );
+    }
+
+    #[test]
+    fn drops_remaining_elements() {
+        let counter = Arc::new(());
+        let queue: StaticTsQueue<_, 4> = StaticTsQueue::new();
+        for _ in 0..3 {
+            queue.enqueue(counter.clone()).unwrap();
+        }
+        drop(queue.dequeue());
+        drop(queue.dequeue());
+        // Wraps around the end of the ring.
+        for _ in 0..3 {
+            queue.enqueue(counter.clone()).unwrap();
+        }
+        drop(queue);
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+}
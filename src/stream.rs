@@ -0,0 +1,115 @@
+use crate::{Closed, Receiver, Sender};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use futures_sink::Sink;
+
+/// Yields the elements of the queue until it is closed and drained.
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let this = self.get_mut();
+        this.shared.queue.poll_dequeue(cx, &mut this.recv_key)
+    }
+}
+
+/// Buffers a single element in `start_send` which is enqueued on the next
+/// `poll_ready` or `poll_flush`. Closing the sink only disconnects this
+/// sender, like dropping it would: the queue is closed once all senders are
+/// closed or dropped. Sending through a closed sink fails.
+impl<T> Sink<T> for Sender<T> {
+    type Error = Closed<T>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Closed<T>>> {
+        self.poll_flush(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Closed<T>> {
+        let this = self.get_mut();
+        if this.disconnected {
+            return Err(Closed(item));
+        }
+        assert!(
+            this.pending.is_none(),
+            "start_send called without poll_ready being ready"
+        );
+        this.pending = Some(item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Closed<T>>> {
+        let this = self.get_mut();
+        if this.pending.is_none() {
+            return Poll::Ready(Ok(()));
+        }
+        this.shared
+            .queue
+            .poll_enqueue(cx, &mut this.pending, &mut this.send_key)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Closed<T>>> {
+        let res = futures_core::ready!(self.as_mut().poll_flush(cx));
+        let this = self.get_mut();
+        if !this.disconnected {
+            this.disconnected = true;
+            this.disconnect();
+        }
+        Poll::Ready(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::roundtrip;
+    use crate::{channel, Closed};
+    use futures::executor::block_on;
+    use futures::{stream, SinkExt, StreamExt};
+
+    #[test]
+    fn sink_into_stream() {
+        let (mut tx, mut rx) = channel();
+        roundtrip(
+            |i| block_on(SinkExt::send(&mut tx, i)).unwrap(),
+            || block_on(rx.next()),
+        );
+        block_on(tx.close()).unwrap();
+        assert_eq!(block_on(rx.next()), None);
+    }
+
+    #[test]
+    fn forward_stream_into_sink() {
+        let (mut tx, rx) = channel();
+        block_on(tx.send_all(&mut stream::iter(0..10).map(Ok))).unwrap();
+        block_on(tx.close()).unwrap();
+        assert_eq!(
+            block_on(rx.collect::<Vec<_>>()),
+            (0..10).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn closing_sink_disconnects_only_its_sender() {
+        let (mut tx, mut rx) = channel();
+        let mut tx2 = tx.clone();
+        block_on(SinkExt::send(&mut tx, 1)).unwrap();
+        block_on(tx.close()).unwrap();
+        assert!(!rx.is_closed());
+        assert_eq!(block_on(SinkExt::send(&mut tx, 2)), Err(Closed(2)));
+
+        block_on(SinkExt::send(&mut tx2, 3)).unwrap();
+        block_on(tx2.close()).unwrap();
+        assert!(rx.is_closed());
+        assert_eq!(block_on((&mut rx).collect::<Vec<_>>()), [1, 3]);
+        drop(tx);
+        drop(tx2);
+        assert_eq!(block_on(rx.next()), None);
+    }
+
+    #[test]
+    fn sink_reports_closed() {
+        let (mut tx, rx) = channel();
+        drop(rx);
+        assert_eq!(block_on(SinkExt::send(&mut tx, 1)), Err(Closed(1)));
+    }
+}
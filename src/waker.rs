@@ -0,0 +1,104 @@
+use crate::lock::{DefaultRawMutex, Mutex, MutexGuard};
+use alloc::collections::VecDeque;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::Waker;
+
+// Registry of tasks waiting for the queue to change state.
+//
+// A task keeps the key handed out by `register`. Notifying a task removes its
+// entry, so a task whose key is no longer registered knows it has been woken.
+pub(crate) struct WakerSet {
+    inner: Mutex<DefaultRawMutex, Inner>,
+    // Mirrors `inner.entries.len()` so that notifying an empty set does not
+    // need to take the lock.
+    len: AtomicUsize,
+}
+
+struct Inner {
+    entries: VecDeque<(usize, Waker)>,
+    next_key: usize,
+}
+
+impl WakerSet {
+    pub(crate) fn new() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                entries: VecDeque::new(),
+                next_key: 0,
+            }),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    // Registers `waker` under `key`, allocating a new key if the task is not
+    // (or no longer) registered.
+    pub(crate) fn register(&self, key: &mut Option<usize>, waker: &Waker) {
+        let mut inner = self.lock();
+        if let Some(k) = *key {
+            if let Some((_, w)) = inner.entries.iter_mut().find(|(e, _)| *e == k) {
+                if !w.will_wake(waker) {
+                    *w = waker.clone();
+                }
+                return;
+            }
+        }
+        let k = inner.next_key;
+        inner.next_key = inner.next_key.wrapping_add(1);
+        inner.entries.push_back((k, waker.clone()));
+        self.len.store(inner.entries.len(), Ordering::Release);
+        *key = Some(k);
+    }
+
+    // Removes the task registered under `key`. Returns `false` if it was
+    // already notified.
+    pub(crate) fn remove(&self, key: usize) -> bool {
+        let mut inner = self.lock();
+        match inner.entries.iter().position(|(e, _)| *e == key) {
+            Some(pos) => {
+                inner.entries.remove(pos);
+                self.len.store(inner.entries.len(), Ordering::Release);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Removes the task registered under `key` when it is dropped before
+    // completing. If it had already been notified, the notification is passed
+    // on to the next task so that it is not lost.
+    pub(crate) fn cancel(&self, key: usize) {
+        if !self.remove(key) {
+            self.notify_one();
+        }
+    }
+
+    // Wakes the longest waiting task.
+    pub(crate) fn notify_one(&self) {
+        if self.len.load(Ordering::Acquire) == 0 {
+            return;
+        }
+        let mut inner = self.lock();
+        if let Some((_, waker)) = inner.entries.pop_front() {
+            self.len.store(inner.entries.len(), Ordering::Release);
+            drop(inner);
+            waker.wake();
+        }
+    }
+
+    pub(crate) fn notify_all(&self) {
+        if self.len.load(Ordering::Acquire) == 0 {
+            return;
+        }
+        let mut inner = self.lock();
+        let entries = core::mem::take(&mut inner.entries);
+        self.len.store(0, Ordering::Release);
+        drop(inner);
+        for (_, waker) in entries {
+            waker.wake();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DefaultRawMutex, Inner> {
+        self.inner.lock()
+    }
+}